@@ -1,7 +1,7 @@
 use std::{fmt::Display, str::FromStr};
 
 use baker::Bake;
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
 #[cfg(test)]
 use fake::Dummy;
@@ -14,7 +14,7 @@ use tap::Pipe;
 #[cfg(feature = "profiling")]
 use tracing::instrument;
 
-use crate::{FILE_EXTENSIONS, PROJECT_DIRS, config::LinkType, helpers};
+use crate::{FILE_EXTENSIONS, FileFormat, PROJECT_DIRS, config::LinkType, helpers};
 
 #[derive(Debug, Clone)]
 #[cfg_attr(test, derive(Dummy, PartialEq, Eq))]
@@ -81,7 +81,7 @@ pub struct Dots {
 
 impl Dots {
   #[cfg_attr(feature = "profiling", instrument)]
-  fn add_root(&self) -> Self {
+  pub(crate) fn add_root(&self) -> Self {
     Self {
       dots: self.dots.iter().map(|d| if d.starts_with('/') { d.to_string() } else { format!("/{d}") }).collect_vec(),
     }
@@ -97,15 +97,83 @@ pub struct LinkRaw {
   pub(crate) dots: Dots,
 
   #[clap(long, short)]
-  /// Force link creation if file already exists and was not created by rotz
+  /// Force link creation if file already exists and was not created by rotz. The existing file is backed up
   pub(crate) force: bool,
 
+  #[clap(long, short)]
+  /// Check all changes before making them and undo every change if one of them fails
+  pub(crate) transactional: bool,
+
   #[clap(long, short)]
   #[baked(ignore)]
   /// Which link type to use for linking dotfiles
   link_type: Option<LinkType>,
 }
 
+#[derive(Debug, Args, Bake, Clone)]
+#[cfg_attr(test, derive(Dummy, PartialEq, Eq))]
+#[baked(name = "Unlink", derive(Debug))]
+pub struct UnlinkRaw {
+  #[clap(flatten)]
+  #[baked(type = "Vec<String>", map_fn(bake = "|l| l.dots.add_root().dots"))]
+  pub(crate) dots: Dots,
+
+  #[clap(long, short)]
+  /// Restore the backups of files which were replaced by the removed links
+  pub(crate) restore: bool,
+
+  #[clap(long, short)]
+  /// Remove targets even if they were modified or not created by rotz. Those targets are moved to the backup store
+  pub(crate) force: bool,
+}
+
+#[derive(Debug, Args, Bake, Clone)]
+#[cfg_attr(test, derive(Dummy, PartialEq, Eq))]
+#[baked(name = "Restore", derive(Debug))]
+pub struct RestoreRaw {
+  /// Targets to restore the backups of. Restores all backups if none are provided.
+  pub(crate) targets: Vec<std::path::PathBuf>,
+}
+
+#[derive(Debug, Args, Bake, Clone)]
+#[cfg_attr(test, derive(Dummy, PartialEq, Eq))]
+#[baked(name = "Status", derive(Debug))]
+pub struct StatusRaw {
+  #[clap(flatten)]
+  #[baked(type = "Vec<String>", map_fn(bake = "|l| l.dots.add_root().dots"))]
+  pub(crate) dots: Dots,
+
+  #[clap(long, short, value_enum, default_value_t = StatusFormat::Human)]
+  /// How the status is printed
+  pub(crate) format: StatusFormat,
+}
+
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(test, derive(Dummy))]
+pub enum StatusFormat {
+  /// Prints the status of the links grouped by dot
+  Human,
+  /// Prints one tab separated line per link with the status, dot, target and source
+  Porcelain,
+  /// Prints the status of the links as a json array
+  #[cfg(feature = "json")]
+  Json,
+}
+
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(test, derive(Dummy))]
+pub enum PlanFormat {
+  /// Prints the install commands in the order they run
+  Human,
+  /// Prints the dependency graph in the Graphviz dot language
+  Dot,
+  /// Prints the dependency graph as a Mermaid flowchart
+  Mermaid,
+  /// Prints the install plan as a json array
+  #[cfg(feature = "json")]
+  Json,
+}
+
 #[derive(Debug, Args, Bake, Clone)]
 #[cfg_attr(test, derive(Dummy, PartialEq, Eq))]
 #[baked(name = "Install", derive(Debug))]
@@ -130,6 +198,118 @@ pub struct InstallRaw {
   /// Do not install any dependencies
   #[clap(long, short = 'a')]
   pub(crate) skip_all_dependencies: bool,
+
+  /// Run install commands again even if they already succeeded and did not change since
+  #[clap(long)]
+  pub(crate) reinstall: bool,
+
+  /// How many install commands run at the same time
+  ///
+  /// Dots are only installed in parallel when they do not depend on each other through their installation dependencies
+  #[clap(long, short, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+  pub(crate) jobs: usize,
+
+  /// Prints what would be installed in which order instead of installing it
+  #[clap(long, value_enum, num_args = 0..=1, default_missing_value = "human", require_equals = true)]
+  pub(crate) plan: Option<PlanFormat>,
+}
+
+#[derive(Debug, Args, Bake, Clone)]
+#[cfg_attr(test, derive(Dummy, PartialEq, Eq))]
+#[baked(name = "Uninstall", derive(Debug))]
+pub struct UninstallRaw {
+  #[clap(required = true)]
+  #[baked(type = "Vec<String>", map_fn(bake = "|l| Dots { dots: l.dots.clone() }.add_root().dots"))]
+  /// The dots to uninstall. Accepts glob patterns, use "**" to uninstall all dots.
+  pub(crate) dots: Vec<String>,
+
+  /// Continues uninstallation when an error occurs during uninstallation
+  #[clap(long, short)]
+  pub(crate) continue_on_error: bool,
+
+  /// Restore the backups of files which were replaced by the removed links
+  #[clap(long, short)]
+  pub(crate) restore: bool,
+}
+
+#[derive(Debug, Args, Bake, Clone)]
+#[cfg_attr(test, derive(Dummy, PartialEq, Eq))]
+#[baked(name = "Update", derive(Debug))]
+pub struct UpdateRaw {
+  #[clap(flatten)]
+  #[baked(type = "Vec<String>", map_fn(bake = "|l| l.dots.add_root().dots"))]
+  pub(crate) dots: Dots,
+
+  /// Continues updating when an error occurs during an update
+  #[clap(long, short)]
+  pub(crate) continue_on_error: bool,
+
+  /// How many update commands run at the same time
+  ///
+  /// Dots are only updated in parallel when they do not depend on each other through their installation dependencies
+  #[clap(long, short, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+  pub(crate) jobs: usize,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+#[cfg_attr(test, derive(Dummy, PartialEq, Eq))]
+pub enum Roots {
+  /// Lists the dotfiles repositories rotz keeps state for
+  List,
+
+  /// Moves the state of another dotfiles repository to the current one
+  ///
+  /// Use this after moving your dotfiles repository and run rotz link afterwards to point the links into the current repository
+  Migrate {
+    /// The path the dotfiles repository was located at
+    from: std::path::PathBuf,
+  },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+#[cfg_attr(test, derive(Dummy, PartialEq, Eq))]
+pub enum State {
+  /// Shows the links, install results and backups rotz recorded
+  Show {
+    #[clap(flatten)]
+    dots: Dots,
+  },
+
+  /// Prints the state or writes it to a file
+  Export {
+    #[clap(long, short, value_parser = |v: &str| FileFormat::try_from(v))]
+    /// Format of the exported state. Defaults to the format of the output file or yaml
+    format: Option<FileFormat>,
+
+    #[clap(long, short)]
+    /// File to write the state to instead of printing it
+    output: Option<std::path::PathBuf>,
+  },
+
+  /// Replaces the state with an exported state file
+  Import {
+    /// The exported state file. Its format is detected from the file extension
+    file: std::path::PathBuf,
+  },
+
+  /// Removes dots from the state without changing the filesystem
+  Forget {
+    #[clap(required = true)]
+    /// The dots to remove. Accepts glob patterns.
+    dots: Vec<String>,
+  },
+
+  /// Removes links whose targets and backups whose files no longer exist from the state
+  Prune,
+
+  /// Adds links which already point to their dotfiles to the state
+  ///
+  /// Use this to rebuild a lost state. Symbolic and hard links pointing to their dotfile and copies with the same content are adopted
+  #[clap(alias = "rebuild")]
+  Adopt {
+    #[clap(flatten)]
+    dots: Dots,
+  },
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -153,12 +333,62 @@ pub enum Command {
     link: LinkRaw,
   },
 
+  /// Removes links created by rotz from the filesystem
+  Unlink {
+    #[clap(flatten)]
+    unlink: UnlinkRaw,
+  },
+
+  /// Restores files which were backed up when they were replaced by a link
+  ///
+  /// If a file was backed up several times the newest backup is restored
+  Restore {
+    #[clap(flatten)]
+    restore: RestoreRaw,
+  },
+
+  /// Shows whether the links on the filesystem match the dotfiles
+  ///
+  /// Exits with a non-zero exit code if any link is out of sync
+  Status {
+    #[clap(flatten)]
+    status: StatusRaw,
+  },
+
+  /// Lists and migrates the state rotz keeps for each dotfiles repository
+  Roots {
+    #[clap(subcommand)]
+    roots: Roots,
+  },
+
+  /// Inspects, exports and repairs the state rotz keeps about links and installs
+  State {
+    #[clap(subcommand)]
+    state: State,
+  },
+
   /// Installs applications using the provided commands
   Install {
     #[clap(flatten)]
     install: InstallRaw,
   },
 
+  /// Runs the uninstall commands of dots and removes their links
+  ///
+  /// Dots are uninstalled before the dots they depend on. Dependencies are not uninstalled
+  Uninstall {
+    #[clap(flatten)]
+    uninstall: UninstallRaw,
+  },
+
+  /// Runs the update commands of dots
+  ///
+  /// Dots are updated after the dots they depend on. Dependencies are not updated
+  Update {
+    #[clap(flatten)]
+    update: UpdateRaw,
+  },
+
   #[clap(verbatim_doc_comment)]
   /// Adds completions to shell
   ///
@@ -1,11 +1,16 @@
 use std::{
-  collections::{HashMap, HashSet},
-  fmt::Debug,
+  collections::{BTreeMap, HashMap, HashSet},
+  fmt::{Debug, Write as _},
+  io::Write,
+  sync::mpsc,
+  thread,
 };
 
 use crossterm::style::{Attribute, Stylize};
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
+use itertools::Itertools;
 use miette::{Diagnostic, Report, Result};
+use serde::Serialize;
 use tap::Pipe;
 #[cfg(feature = "profiling")]
 use tracing::instrument;
@@ -13,17 +18,22 @@ use velcro::hash_map;
 use wax::{Glob, Pattern};
 
 use super::Command;
-use crate::{config::Config, dot::Installs, helpers, templating};
+use crate::{
+  cli::PlanFormat,
+  config::Config,
+  dot::{Dot, Installs},
+  helpers, state, templating,
+};
 
 #[derive(thiserror::Error, Diagnostic, Debug)]
 enum Error {
   #[error("{name} has a cyclic dependency")]
-  #[diagnostic(code(dependency::cyclic), help("{} depends on itsself through {}", name, through))]
-  CyclicDependency { name: String, through: String },
+  #[diagnostic(code(dependency::cyclic), help("The dependencies form the cycle {cycle}"))]
+  CyclicDependency { name: String, cycle: String },
 
   #[error("{name} has a cyclic installation dependency")]
-  #[diagnostic(code(dependency::cyclic::install), help("{} depends on itsself through {}", name, through))]
-  CyclicInstallDependency { name: String, through: String },
+  #[diagnostic(code(dependency::cyclic::install), help("The dependencies form the cycle {cycle}"))]
+  CyclicInstallDependency { name: String, cycle: String },
 
   #[error("Dependency {1} of {0} was not found")]
   #[diagnostic(code(dependency::not_found))]
@@ -46,13 +56,48 @@ enum Error {
   #[diagnostic(code(install::command::parse))]
   ParsingInstallCommand(String, #[source] shellwords::MismatchedQuotes),
 
+  #[error("Check for {0} did not succeed after installing it")]
+  #[diagnostic(code(install::check), help("Make sure the check or binary of {0} matches what its install command installs"))]
+  CheckFailed(String),
+
+  #[error("Uninstall command for {0} did not run successfully")]
+  #[diagnostic(code(uninstall::command::run))]
+  UninstallExecute(
+    String,
+    #[source]
+    #[diagnostic_source]
+    helpers::RunError,
+  ),
+
+  #[error("Update command for {0} did not run successfully")]
+  #[diagnostic(code(update::command::run))]
+  UpdateExecute(
+    String,
+    #[source]
+    #[diagnostic_source]
+    helpers::RunError,
+  ),
+
+  #[error("Check for {0} still succeeds after uninstalling it")]
+  #[diagnostic(code(uninstall::check), help("Make sure the check or binary of {0} matches what its uninstall command removes"))]
+  UninstallCheckFailed(String),
+
   #[error("Could not spawl install command")]
   #[diagnostic(code(install::command::spawn), help("The shell_command in your config is set to \"{0}\" is that correct?"))]
   CouldNotSpawn(String),
 
+  #[error(transparent)]
+  #[diagnostic(transparent)]
+  State(state::Error),
+
   #[error("Could not parse dependency \"{0}\"")]
   #[diagnostic(code(glob::parse))]
   ParseGlob(String, #[source] Box<wax::BuildError>),
+
+  #[cfg(feature = "json")]
+  #[error("Could not serialize the install plan")]
+  #[diagnostic(code(install::plan::serialize))]
+  Serializing(#[source] serde_json::Error),
 }
 
 pub(crate) struct Install<'a> {
@@ -71,97 +116,196 @@ impl<'b> Install<'b> {
     Self { config, engine }
   }
 
+  /// Wraps the command `cmd` of `name` in the configured shell command
   #[cfg_attr(feature = "profiling", instrument)]
-  fn install<'a>(
-    &self,
-    dots: &'a HashMap<String, InstallsDots>,
-    entry: (&'a String, &'a InstallsDots),
-    installed: &mut HashSet<&'a str>,
-    mut stack: IndexSet<String>,
-    (globals, install_command): (&crate::cli::Globals, &crate::cli::Install),
-  ) -> Result<(), Error> {
-    if installed.contains(entry.0.as_str()) {
-      return ().pipe(Ok);
-    }
-
-    stack.insert(entry.0.clone());
-
-    macro_rules! recurse {
-      ($depends:expr, $error:ident) => {
-        for dependency in $depends {
-          let dependency_glob = Glob::new(dependency).map_err(|e| Error::ParseGlob(dependency.clone(), e.into()))?;
-
-          if stack.iter().any(|d| dependency_glob.is_match(&**d)) {
-            return Error::$error {
-              name: dependency.clone(),
-              through: entry.0.clone(),
-            }
-            .pipe(Err);
-          }
+  fn render(&self, name: &str, cmd: &str) -> Result<String, Error> {
+    self.config.shell_command.as_ref().map_or_else(
+      || cmd.to_owned().pipe(Ok),
+      |shell_command| {
+        self
+          .engine
+          .render_template(shell_command, &hash_map! { "cmd": &cmd })
+          .map_err(|err| Error::RenderingTemplate(name.to_owned(), err.pipe(Box::new)))
+      },
+    )
+  }
 
-          self.install(
-            dots,
-            (
-              dependency,
-              dots
-                .iter()
-                .find(|d| dependency_glob.is_match(&**d.0))
-                .map(|d| d.1)
-                .ok_or_else(|| Error::DependencyNotFound(entry.0.clone(), dependency.clone()))?,
-            ),
-            installed,
-            stack.clone(),
-            (globals, install_command),
-          )?;
-        }
-      };
+  /// Renders the install command and the check of `name`
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn prepare(&self, name: String, installs: &Installs) -> Result<Job, Error> {
+    let inner_cmd = installs.cmd.clone();
+    let cmd = self.render(&name, &inner_cmd)?;
+
+    let hash = helpers::hash(&cmd);
+    let args = shellwords::split(&cmd).map_err(|err| Error::ParsingInstallCommand(name.clone(), err))?;
+
+    let check = Check {
+      cmd: installs
+        .check
+        .as_ref()
+        .map(|check| self.render(&name, check))
+        .transpose()?
+        .map(|check| shellwords::split(&check).map_err(|err| Error::ParsingInstallCommand(name.clone(), err)))
+        .transpose()?
+        .filter(|check| !check.is_empty()),
+      binary: installs.binary.clone(),
+    };
+
+    Job {
+      name,
+      cmd,
+      args,
+      inner_cmd,
+      hash,
+      check,
     }
+    .pipe(Ok)
+  }
 
-    if let Some(installs) = &entry.1.0 {
-      if !(install_command.skip_all_dependencies || install_command.skip_installation_dependencies) {
-        recurse!(&installs.depends, CyclicInstallDependency);
-      }
-
-      println!("{}Installing {}{}\n", Attribute::Bold, entry.0.as_str().blue(), Attribute::Reset);
+  /// Reads all dots of the dotfiles repository
+  #[cfg_attr(feature = "profiling", instrument)]
+  pub(super) fn dots(&self) -> Result<BTreeMap<String, Dot>> {
+    crate::dot::read_dots(&self.config.dotfiles, &["/**".to_owned()], &self.config, &self.engine)?
+      .into_iter()
+      .collect::<BTreeMap<_, _>>()
+      .pipe(Ok)
+  }
 
-      let inner_cmd = installs.cmd.clone();
+  /// Lists the dots in `graph` in installation order together with their rendered install commands and why they would be skipped
+  #[cfg_attr(feature = "profiling", instrument(skip(state)))]
+  fn plan(&self, graph: Graph, state: &state::State, (globals, options): (&crate::cli::Globals, &Options)) -> Result<Vec<Step>, Error> {
+    graph
+      .into_iter()
+      .map(|(name, node)| {
+        let job = node.installs.map(|installs| self.prepare(name.clone(), &installs)).transpose()?;
+
+        Step {
+          skip: job.as_ref().and_then(|job| skip_reason(job, Action::Install, state, (globals, options))),
+          cmd: job.map(|job| job.cmd),
+          dot: name,
+          after: node.after.into_iter().collect(),
+          depends: node.depends.into_iter().collect(),
+        }
+        .pipe(Ok)
+      })
+      .collect()
+  }
 
-      let cmd = if let Some(shell_command) = self.config.shell_command.as_ref() {
-        self
-          .engine
-          .render_template(shell_command, &hash_map! { "cmd": &inner_cmd })
-          .map_err(|err| Error::RenderingTemplate(entry.0.clone(), err.pipe(Box::new)))?
-      } else {
-        #[allow(clippy::redundant_clone)]
-        inner_cmd.clone()
-      };
+  /// Runs the commands in `graph` with up to `jobs` commands at the same time and records their results
+  ///
+  /// A dot is processed as soon as all dots in its `after` finished
+  #[cfg_attr(feature = "profiling", instrument(skip(state)))]
+  pub(super) fn run(&self, mut graph: Graph, action: Action, state: &mut state::State, (globals, options): (&crate::cli::Globals, &Options)) -> miette::Result<()> {
+    let (sender, receiver) = mpsc::channel::<(Job, Vec<u8>, Result<(), helpers::RunError>)>();
+    let mut finished = HashSet::new();
+    let mut running = 0;
+    let mut error = None;
+
+    thread::scope(|scope| {
+      loop {
+        while error.is_none() && running < options.jobs {
+          let Some(index) = graph.values().position(|node| node.after.iter().all(|a| finished.contains(a))) else {
+            break;
+          };
+          let (name, node) = graph.shift_remove_index(index).expect("index is in bounds");
+          let Some(installs) = node.installs else {
+            finished.insert(name);
+            continue;
+          };
+
+          let job = match state::check_interrupted().map_err(Error::State).and_then(|()| self.prepare(name.clone(), &installs)) {
+            Ok(job) => match skip_reason(&job, action, state, (globals, options)) {
+              Some(reason) => {
+                println!("{}Skipping {}{} {}\n", Attribute::Bold, name.as_str().blue(), Attribute::Reset, reason.dark_green());
+                finished.insert(name);
+                continue;
+              }
+              None => job,
+            },
+            Err(err) => {
+              error = Some(err);
+              break;
+            }
+          };
 
-      let cmd = shellwords::split(&cmd).map_err(|err| Error::ParsingInstallCommand(entry.0.clone(), err))?;
+          println!("{}{} {}{}\n", Attribute::Bold, action.verb(), job.name.as_str().blue(), Attribute::Reset);
+          println!("{}{}{}\n", Attribute::Italic, job.inner_cmd, Attribute::Reset);
 
-      println!("{}{inner_cmd}{}\n", Attribute::Italic, Attribute::Reset);
+          let sender = sender.clone();
+          scope.spawn(move || {
+            let (output, result) = helpers::run_command_buffered(&job.args[0], &job.args[1..], globals.dry_run);
+            sender.send((job, output, result)).expect("receiver outlives the install threads");
+          });
+          running += 1;
+        }
 
-      if let Err(err) = helpers::run_command(&cmd[0], &cmd[1..], false, globals.dry_run) {
-        if let helpers::RunError::Spawn(err) = &err {
-          if err.kind() == std::io::ErrorKind::NotFound {
-            eprintln!("\n Error: {:?}", Report::new(Error::CouldNotSpawn(format!("{:?}", self.config.shell_command))));
-          }
+        if running == 0 {
+          break;
         }
 
-        let error = Error::InstallExecute(entry.0.clone(), err);
+        let (job, output, result) = receiver.recv().expect("install threads send their result");
+        running -= 1;
 
-        if install_command.continue_on_error {
-          eprintln!("\n Error: {:?}", Report::new(error));
-        } else {
-          return error.pipe(Err);
+        print_output(&job.name, &output, options.jobs > 1);
+        finished.insert(job.name.clone());
+
+        if let Err(err) = self.record(job, result, action, state, (globals, options)) {
+          error.get_or_insert(err);
         }
       }
+    });
+
+    error.map_or(Ok(()), |error| Err(error.into()))
+  }
 
-      installed.insert(entry.0.as_str());
+  /// Records the result of the command in `job` in the state
+  ///
+  /// A command which succeeded is recorded as failed if the check of the dot does not match afterwards
+  #[cfg_attr(feature = "profiling", instrument(skip(state)))]
+  fn record(&self, job: Job, result: Result<(), helpers::RunError>, action: Action, state: &mut state::State, (globals, options): (&crate::cli::Globals, &Options)) -> Result<(), Error> {
+    if let Err(helpers::RunError::Spawn(err)) = &result {
+      if err.kind() == std::io::ErrorKind::NotFound {
+        eprintln!("\n Error: {:?}", Report::new(Error::CouldNotSpawn(format!("{:?}", self.config.shell_command))));
+      }
     }
 
-    if !(install_command.skip_all_dependencies || install_command.skip_dependencies) {
-      if let Some(depends) = &entry.1.1 {
-        recurse!(depends, CyclicDependency);
+    let result = match (action, result) {
+      (Action::Install, Err(err)) => Error::InstallExecute(job.name.clone(), err).pipe(Err),
+      (Action::Uninstall, Err(err)) => Error::UninstallExecute(job.name.clone(), err).pipe(Err),
+      (Action::Update, Err(err)) => Error::UpdateExecute(job.name.clone(), err).pipe(Err),
+      (Action::Install, Ok(())) if !globals.dry_run && job.check.satisfied() == Some(false) => Error::CheckFailed(job.name.clone()).pipe(Err),
+      (Action::Uninstall, Ok(())) if !globals.dry_run && job.check.satisfied() == Some(true) => Error::UninstallCheckFailed(job.name.clone()).pipe(Err),
+      (_, Ok(())) => ().pipe(Ok),
+    };
+
+    if !globals.dry_run {
+      match action {
+        Action::Install => {
+          state.installed.0.insert(
+            job.name,
+            state::Install {
+              hash: job.hash,
+              time: state::timestamp(),
+              success: result.is_ok(),
+              rotz_version: env!("CARGO_PKG_VERSION").to_owned(),
+            },
+          );
+        }
+        Action::Uninstall => {
+          if result.is_ok() {
+            state.installed.0.remove(&job.name);
+          }
+        }
+        Action::Update => {}
+      }
+      state.commit().map_err(Error::State)?;
+    }
+
+    if let Err(error) = result {
+      if options.continue_on_error {
+        eprintln!("\n Error: {:?}", Report::new(error));
+      } else {
+        return error.pipe(Err);
       }
     }
 
@@ -169,28 +313,647 @@ impl<'b> Install<'b> {
   }
 }
 
+/// An install command which is ready to run
+#[derive(Debug)]
+struct Job {
+  name: String,
+  cmd: String,
+  args: Vec<String>,
+  inner_cmd: String,
+  hash: String,
+  check: Check,
+}
+
+/// Checks whether a dot is installed already
+#[derive(Debug)]
+struct Check {
+  cmd: Option<Vec<String>>,
+  binary: Option<String>,
+}
+
+impl Check {
+  /// Runs the check and returns [`None`] if the dot has nothing to check
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn satisfied(&self) -> Option<bool> {
+    if self.cmd.is_none() && self.binary.is_none() {
+      return None;
+    }
+
+    (self.binary.as_ref().is_none_or(|binary| helpers::find_binary(binary).is_some()) && self.cmd.as_ref().is_none_or(|cmd| helpers::run_command_buffered(&cmd[0], &cmd[1..], false).1.is_ok()))
+      .pipe(Some)
+  }
+}
+
+/// Which command of the dots runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Action {
+  Install,
+  Uninstall,
+  Update,
+}
+
+impl Action {
+  const fn verb(self) -> &'static str {
+    match self {
+      Self::Install => "Installing",
+      Self::Uninstall => "Uninstalling",
+      Self::Update => "Updating",
+    }
+  }
+}
+
+/// How the commands of the dots run
+#[derive(Debug)]
+pub(super) struct Options {
+  pub(super) continue_on_error: bool,
+  pub(super) reinstall: bool,
+  pub(super) jobs: usize,
+}
+
+impl From<&crate::cli::Install> for Options {
+  fn from(install_command: &crate::cli::Install) -> Self {
+    Self {
+      continue_on_error: install_command.continue_on_error,
+      reinstall: install_command.reinstall,
+      jobs: install_command.jobs,
+    }
+  }
+}
+
+/// Whether the install command in `job` already succeeded and does not need to run again
+fn is_installed(job: &Job, state: &state::State, options: &Options) -> bool {
+  !options.reinstall && state.installed.0.get(&job.name).is_some_and(|r| r.success && r.hash == job.hash)
+}
+
+/// Why the command in `job` does not need to run, if it does not
+///
+/// When installing the check of the dot decides if it has one, otherwise the result of the last installation in the state is used.
+/// Uninstalling and updating is skipped if the check of the dot fails. Checks do not run on dry runs
+fn skip_reason(job: &Job, action: Action, state: &state::State, (globals, options): (&crate::cli::Globals, &Options)) -> Option<&'static str> {
+  match action {
+    Action::Install if options.reinstall => None,
+    Action::Install if globals.dry_run => is_installed(job, state, options).then_some("already installed"),
+    Action::Install => match job.check.satisfied() {
+      Some(true) => Some("already satisfied"),
+      Some(false) => None,
+      None => is_installed(job, state, options).then_some("already installed"),
+    },
+    Action::Uninstall | Action::Update => (!globals.dry_run && job.check.satisfied() == Some(false)).then_some("not installed"),
+  }
+}
+
+/// A dot in the installation graph
+#[derive(Debug)]
+pub(super) struct Node {
+  installs: Option<Installs>,
+  /// Installation dependencies which have to be installed before this dot
+  after: IndexSet<String>,
+  /// Dependencies which are installed together with this dot
+  depends: IndexSet<String>,
+}
+
+/// A dot in the install plan
+#[derive(Serialize, Debug)]
+struct Step {
+  dot: String,
+  cmd: Option<String>,
+  /// Why the install command would not run
+  skip: Option<&'static str>,
+  after: Vec<String>,
+  depends: Vec<String>,
+}
+
+/// The dots to install in the order they would be installed one after another
+pub(super) type Graph = IndexMap<String, Node>;
+
+/// Prints the `output` of the install command of `name`, prefixed with the name if multiple commands run at the same time
+fn print_output(name: &str, output: &[u8], prefixed: bool) {
+  let output = String::from_utf8_lossy(output);
+  let mut stdout = std::io::stdout().lock();
+
+  if prefixed {
+    let prefix = format!("[{name}]").dark_grey();
+    for line in output.lines() {
+      writeln!(stdout, "{prefix} {line}").ok();
+    }
+  } else {
+    write!(stdout, "{output}").ok();
+  }
+}
+
+fn print_plan(steps: &[Step]) {
+  for (index, step) in steps.iter().filter(|s| s.cmd.is_some()).enumerate() {
+    print!("{}{}. {}{}", Attribute::Bold, index + 1, step.dot.as_str().blue(), Attribute::Reset);
+    if let Some(skip) = step.skip {
+      print!(" {}", skip.dark_green());
+    }
+    if !step.after.is_empty() {
+      print!(" {}", format!("after {}", step.after.join(", ")).dark_grey());
+    }
+    println!();
+
+    if let Some(cmd) = &step.cmd {
+      println!("   {}{cmd}{}\n", Attribute::Italic, Attribute::Reset);
+    }
+  }
+}
+
+/// Renders the install plan as a Graphviz graph. Edges point from a dot to its dependencies, dependencies which are not installation dependencies are dashed
+fn graphviz(steps: &[Step]) -> String {
+  let mut graph = "digraph rotz {\n".to_owned();
+
+  for step in steps {
+    let style = if step.skip.is_some() { ", style=filled" } else { "" };
+    writeln!(graph, "  {:?} [shape={}{style}];", step.dot, if step.cmd.is_some() { "box" } else { "ellipse" }).ok();
+  }
+  for step in steps {
+    for after in &step.after {
+      writeln!(graph, "  {:?} -> {after:?};", step.dot).ok();
+    }
+    for depends in &step.depends {
+      writeln!(graph, "  {:?} -> {depends:?} [style=dashed];", step.dot).ok();
+    }
+  }
+
+  graph + "}\n"
+}
+
+/// Renders the install plan as a Mermaid flowchart. Edges point from a dot to its dependencies, dependencies which are not installation dependencies are dotted
+fn mermaid(steps: &[Step]) -> String {
+  let ids = steps.iter().enumerate().map(|(index, step)| (step.dot.as_str(), format!("dot{index}"))).collect::<HashMap<_, _>>();
+  let mut graph = "flowchart TD\n".to_owned();
+
+  for step in steps {
+    let label = step.dot.replace('"', "#quot;");
+    let label = if step.skip.is_some() { format!("{label} ✓") } else { label };
+    writeln!(graph, "  {}[\"{label}\"]", ids[step.dot.as_str()]).ok();
+  }
+  for step in steps {
+    for after in &step.after {
+      writeln!(graph, "  {} --> {}", ids[step.dot.as_str()], ids[after.as_str()]).ok();
+    }
+    for depends in &step.depends {
+      writeln!(graph, "  {} -.-> {}", ids[step.dot.as_str()], ids[depends.as_str()]).ok();
+    }
+  }
+
+  graph
+}
+
+/// Resolves `dependency` of `name` to a dot in `dots`
+///
+/// Fails with the error built by `cyclic` from the name of the first dot in the cycle and the full cycle if `dependency` is already in `stack`
+fn resolve<'a, T>(dots: &'a BTreeMap<String, T>, name: &str, dependency: &str, stack: &IndexSet<String>, cyclic: fn(String, String) -> Error) -> Result<&'a str, Error> {
+  let dependency_glob = Glob::new(dependency).map_err(|e| Error::ParseGlob(dependency.to_owned(), e.into()))?;
+
+  if let Some(start) = stack.iter().position(|d| dependency_glob.is_match(&**d)) {
+    let cycle = stack.iter().skip(start).chain(stack.get_index(start)).join(" -> ");
+    return cyclic(stack[start].clone(), cycle).pipe(Err);
+  }
+
+  dots
+    .keys()
+    .find(|d| dependency_glob.is_match(d.as_str()))
+    .map(String::as_str)
+    .ok_or_else(|| Error::DependencyNotFound(name.to_owned(), dependency.to_owned()))
+}
+
+/// Adds `name` and its dependencies to `graph`
+///
+/// Installation dependencies are added before `name` and have to finish before it is installed. Dependencies are added after `name` without any ordering
+#[cfg_attr(feature = "profiling", instrument(skip(dots, graph)))]
+fn visit(dots: &BTreeMap<String, InstallsDots>, name: &str, graph: &mut Graph, mut stack: IndexSet<String>, skip: (bool, bool)) -> Result<(), Error> {
+  if graph.contains_key(name) {
+    return ().pipe(Ok);
+  }
+
+  stack.insert(name.to_owned());
+  let (installs, depends) = &dots[name];
+
+  let mut after = IndexSet::new();
+  if let Some(installs) = installs {
+    if !skip.0 {
+      for dependency in installs.depends.iter().sorted() {
+        let dependency = resolve(dots, name, dependency, &stack, |name, cycle| Error::CyclicInstallDependency { name, cycle })?;
+        visit(dots, dependency, graph, stack.clone(), skip)?;
+        after.insert(dependency.to_owned());
+      }
+    }
+  }
+
+  graph.insert(
+    name.to_owned(),
+    Node {
+      installs: installs.clone(),
+      after,
+      depends: IndexSet::new(),
+    },
+  );
+
+  if !skip.1 {
+    for dependency in depends.iter().flatten().sorted() {
+      let dependency = resolve(dots, name, dependency, &stack, |name, cycle| Error::CyclicDependency { name, cycle })?;
+      visit(dots, dependency, graph, stack.clone(), skip)?;
+      graph[name].depends.insert(dependency.to_owned());
+    }
+  }
+
+  ().pipe(Ok)
+}
+
+/// Builds the installation graph of the dots in `dots` which match the globs in `selection`
+///
+/// `skip` controls whether installation dependencies and dependencies are left out
+#[cfg_attr(feature = "profiling", instrument(skip(dots)))]
+fn graph(dots: &BTreeMap<String, InstallsDots>, selection: &[String], skip: (bool, bool)) -> Result<Graph> {
+  let globs = helpers::glob_from_vec(selection, None)?;
+
+  let mut graph = Graph::new();
+  for name in dots.keys().filter(|name| globs.is_match(name.as_str())) {
+    visit(dots, name, &mut graph, IndexSet::new(), skip)?;
+  }
+
+  graph.pipe(Ok)
+}
+
+/// Maps every dot in `dots` to the command selected by `commands` for ordering them
+///
+/// The command depends on the installation dependencies of the dot as well as on its own. Dots without the command keep their install command so the order through them is kept
+fn ordering(dots: &BTreeMap<String, Dot>, commands: impl Fn(&Dot) -> Option<&Installs>) -> BTreeMap<String, InstallsDots> {
+  dots
+    .iter()
+    .map(|(name, dot)| {
+      let installs = commands(dot).cloned().map_or_else(
+        || dot.installs.clone(),
+        |mut commands| {
+          commands.depends.extend(dot.installs.iter().flat_map(|i| i.depends.iter().cloned()));
+          Some(commands)
+        },
+      );
+
+      (name.clone(), (installs, None))
+    })
+    .collect()
+}
+
+/// Keeps the dots in `graph` for which `keep` returns true
+///
+/// Dots which waited for a removed dot wait for the dots the removed dot waited for instead
+fn restrict(graph: Graph, keep: impl Fn(&str) -> bool) -> Graph {
+  let mut removed = HashMap::<String, IndexSet<String>>::new();
+  let mut restricted = Graph::new();
+
+  for (name, node) in graph {
+    let after = node
+      .after
+      .iter()
+      .flat_map(|after| removed.get(after).cloned().unwrap_or_else(|| IndexSet::from([after.clone()])))
+      .collect::<IndexSet<_>>();
+
+    if keep(&name) {
+      restricted.insert(
+        name,
+        Node {
+          installs: node.installs,
+          after,
+          depends: IndexSet::new(),
+        },
+      );
+    } else {
+      removed.insert(name, after);
+    }
+  }
+
+  restricted
+}
+
+/// Builds the graph to uninstall the dots in `dots` which match the globs in `selection`
+///
+/// Dots are uninstalled in reverse installation order so every dot is uninstalled before the dots its `installs` or `uninstalls` depend on. Dependencies are not uninstalled
+#[cfg_attr(feature = "profiling", instrument(skip(dots)))]
+pub(super) fn uninstall_graph(dots: &BTreeMap<String, Dot>, selection: &[String]) -> Result<Graph> {
+  let globs = helpers::glob_from_vec(selection, None)?;
+
+  graph(&ordering(dots, |dot| dot.uninstalls.as_ref()), &["/**".to_owned()], (false, true))?
+    .into_iter()
+    .rev()
+    .filter(|(name, _)| globs.is_match(name.as_str()) && dots[name].uninstalls.is_some())
+    .map(|(name, node)| {
+      (
+        name,
+        Node {
+          installs: node.installs,
+          after: IndexSet::new(),
+          depends: IndexSet::new(),
+        },
+      )
+    })
+    .collect::<Graph>()
+    .pipe(Ok)
+}
+
+/// Builds the graph to update the dots in `dots` which match the globs in `selection`
+///
+/// Dots are updated after the dots their `installs` or `updates` depend on. Dependencies are not updated
+#[cfg_attr(feature = "profiling", instrument(skip(dots)))]
+pub(super) fn update_graph(dots: &BTreeMap<String, Dot>, selection: &[String]) -> Result<Graph> {
+  let globs = helpers::glob_from_vec(selection, None)?;
+
+  graph(&ordering(dots, |dot| dot.updates.as_ref()), selection, (false, true))?
+    .pipe(|graph| restrict(graph, |name| globs.is_match(name) && dots[name].updates.is_some()))
+    .pipe(Ok)
+}
+
 type InstallsDots = (Option<Installs>, Option<HashSet<String>>);
 
 impl Command for Install<'_> {
-  type Args = (crate::cli::Globals, crate::cli::Install);
-  type Result = Result<()>;
+  type Args = (crate::cli::Globals, crate::cli::Install, state::State);
+  type Result = Result<state::State>;
 
   #[cfg_attr(feature = "profiling", instrument)]
-  fn execute(&self, (globals, install_command): Self::Args) -> Self::Result {
-    let dots = crate::dot::read_dots(&self.config.dotfiles, &["/**".to_owned()], &self.config, &self.engine)?
+  fn execute(&self, (globals, install_command, mut state): Self::Args) -> Self::Result {
+    let dots = self
+      .dots()?
       .into_iter()
       .filter(|d| d.1.installs.is_some() || d.1.depends.is_some())
       .map(|d| (d.0, (d.1.installs, d.1.depends)))
-      .collect::<HashMap<String, InstallsDots>>();
-
-    let mut installed: HashSet<&str> = HashSet::new();
-    let globs = helpers::glob_from_vec(&install_command.dots, None)?;
-    for dot in &dots {
-      if globs.is_match(dot.0.as_str()) {
-        self.install(&dots, dot, &mut installed, IndexSet::new(), (&globals, &install_command))?;
+      .collect::<BTreeMap<String, InstallsDots>>();
+
+    let skip = (
+      install_command.skip_all_dependencies || install_command.skip_installation_dependencies,
+      install_command.skip_all_dependencies || install_command.skip_dependencies,
+    );
+    let graph = graph(&dots, &install_command.dots, skip)?;
+    let options = Options::from(&install_command);
+
+    if let Some(format) = install_command.plan {
+      let steps = self.plan(graph, &state, (&globals, &options))?;
+      match format {
+        PlanFormat::Human => print_plan(&steps),
+        PlanFormat::Dot => print!("{}", graphviz(&steps)),
+        PlanFormat::Mermaid => print!("{}", mermaid(&steps)),
+        #[cfg(feature = "json")]
+        PlanFormat::Json => println!("{}", serde_json::to_string_pretty(&steps).map_err(Error::Serializing)?),
       }
+
+      return state.pipe(Ok);
     }
 
-    ().pipe(Ok)
+    self.run(graph, Action::Install, &mut state, (&globals, &options))?;
+
+    state.pipe(Ok)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::{BTreeMap, HashSet};
+
+  use indexmap::IndexSet;
+  use itertools::Itertools;
+  use rstest::rstest;
+  use speculoos::prelude::*;
+
+  use super::{Action, Check, Error, InstallsDots, Job, Options, Step, graph, graphviz, mermaid, resolve, skip_reason, uninstall_graph, update_graph};
+  use crate::{
+    cli::Globals,
+    dot::{Dot, Installs},
+    state,
+  };
+
+  fn dot(depends: &[&str], installs_depends: Option<&[&str]>) -> InstallsDots {
+    (
+      installs_depends.map(|d| Installs {
+        cmd: "true".to_owned(),
+        depends: d.iter().map(ToString::to_string).collect(),
+        check: None,
+        binary: None,
+      }),
+      Some(depends.iter().map(ToString::to_string).collect::<HashSet<_>>()),
+    )
+  }
+
+  #[test]
+  fn installation_dependencies_come_first() {
+    let dots = BTreeMap::from([
+      ("/a".to_owned(), dot(&["/c"], Some(&["/b"]))),
+      ("/b".to_owned(), dot(&[], Some(&[]))),
+      ("/c".to_owned(), dot(&[], Some(&[]))),
+      ("/d".to_owned(), dot(&[], Some(&[]))),
+    ]);
+
+    let graph = graph(&dots, &["/a".to_owned()], (false, false)).unwrap();
+
+    assert_that!(graph.keys().map(String::as_str).collect_vec()).is_equal_to(vec!["/b", "/a", "/c"]);
+    assert_that!(graph["/a"].after.iter().map(String::as_str).collect_vec()).is_equal_to(vec!["/b"]);
+    assert_that!(graph["/c"].after.is_empty()).is_true();
+  }
+
+  #[test]
+  fn uninstall_in_reverse_order() {
+    let uninstalls = |depends: &[&str]| {
+      Some(Installs {
+        cmd: "true".to_owned(),
+        depends: depends.iter().map(ToString::to_string).collect(),
+        check: None,
+        binary: None,
+      })
+    };
+    let dots = BTreeMap::from([
+      (
+        "/a".to_owned(),
+        Dot {
+          installs: dot(&[], Some(&["/b"])).0,
+          uninstalls: uninstalls(&[]),
+          ..Dot::default()
+        },
+      ),
+      (
+        "/b".to_owned(),
+        Dot {
+          uninstalls: uninstalls(&["/c"]),
+          ..Dot::default()
+        },
+      ),
+      (
+        "/c".to_owned(),
+        Dot {
+          uninstalls: uninstalls(&[]),
+          ..Dot::default()
+        },
+      ),
+      ("/d".to_owned(), Dot::default()),
+    ]);
+
+    let graph = uninstall_graph(&dots, &["/**".to_owned()]).unwrap();
+    assert_that!(graph.keys().map(String::as_str).collect_vec()).is_equal_to(vec!["/a", "/b", "/c"]);
+
+    let graph = uninstall_graph(&dots, &["/c".to_owned(), "/a".to_owned()]).unwrap();
+    assert_that!(graph.keys().map(String::as_str).collect_vec()).is_equal_to(vec!["/a", "/c"]);
+  }
+
+  #[test]
+  fn update_after_dependencies() {
+    let updates = Some(Installs {
+      cmd: "true".to_owned(),
+      depends: HashSet::new(),
+      check: None,
+      binary: None,
+    });
+    let dots = BTreeMap::from([
+      (
+        "/a".to_owned(),
+        Dot {
+          installs: dot(&[], Some(&["/b"])).0,
+          updates: updates.clone(),
+          ..Dot::default()
+        },
+      ),
+      (
+        "/b".to_owned(),
+        Dot {
+          installs: dot(&[], Some(&["/c"])).0,
+          ..Dot::default()
+        },
+      ),
+      (
+        "/c".to_owned(),
+        Dot {
+          updates: updates.clone(),
+          ..Dot::default()
+        },
+      ),
+      ("/d".to_owned(), Dot { updates, ..Dot::default() }),
+    ]);
+
+    let graph = update_graph(&dots, &["/**".to_owned()]).unwrap();
+    assert_that!(graph.keys().map(String::as_str).collect_vec()).is_equal_to(vec!["/c", "/a", "/d"]);
+    assert_that!(graph["/a"].after.iter().map(String::as_str).collect_vec()).is_equal_to(vec!["/c"]);
+
+    let graph = update_graph(&dots, &["/a".to_owned()]).unwrap();
+    assert_that!(graph.keys().map(String::as_str).collect_vec()).is_equal_to(vec!["/a"]);
+  }
+
+  #[test]
+  fn cyclic_installation_dependencies() {
+    let dots = BTreeMap::from([("/a".to_owned(), dot(&[], Some(&["/b"]))), ("/b".to_owned(), dot(&[], Some(&["/a"])))]);
+
+    assert_that!(graph(&dots, &["/**".to_owned()], (false, false))).is_err();
+  }
+
+  #[test]
+  fn cycle_contains_full_path() {
+    let dots = BTreeMap::from([
+      ("/a".to_owned(), dot(&[], Some(&["/b"]))),
+      ("/b".to_owned(), dot(&["/c"], Some(&[]))),
+      ("/c".to_owned(), dot(&["/a"], None)),
+    ]);
+
+    let mut stack = IndexSet::new();
+    stack.extend(["/a".to_owned(), "/b".to_owned(), "/c".to_owned()]);
+
+    assert_that!(resolve(&dots, "/c", "/a", &stack, |name, cycle| Error::CyclicDependency { name, cycle }))
+      .is_err()
+      .matches(|e| matches!(e, Error::CyclicDependency { name, cycle } if name == "/a" && cycle == "/a -> /b -> /c -> /a"));
+  }
+
+  #[test]
+  fn plan_as_graph() {
+    let steps = vec![
+      Step {
+        dot: "/b".to_owned(),
+        cmd: Some("true".to_owned()),
+        skip: Some("already installed"),
+        after: vec![],
+        depends: vec![],
+      },
+      Step {
+        dot: "/a".to_owned(),
+        cmd: Some("true".to_owned()),
+        skip: None,
+        after: vec!["/b".to_owned()],
+        depends: vec!["/c".to_owned()],
+      },
+      Step {
+        dot: "/c".to_owned(),
+        cmd: None,
+        skip: None,
+        after: vec![],
+        depends: vec![],
+      },
+    ];
+
+    assert_that!(graphviz(&steps)).is_equal_to(
+      r#"digraph rotz {
+  "/b" [shape=box, style=filled];
+  "/a" [shape=box];
+  "/c" [shape=ellipse];
+  "/a" -> "/b";
+  "/a" -> "/c" [style=dashed];
+}
+"#
+      .to_owned(),
+    );
+
+    assert_that!(mermaid(&steps)).is_equal_to(
+      r#"flowchart TD
+  dot0["/b ✓"]
+  dot1["/a"]
+  dot2["/c"]
+  dot1 --> dot0
+  dot1 -.-> dot2
+"#
+      .to_owned(),
+    );
+  }
+
+  #[rstest]
+  #[case::not_installed(Action::Install, (false, false), None, None, None)]
+  #[case::installed(Action::Install, (false, false), None, Some("hash"), Some("already installed"))]
+  #[case::command_changed(Action::Install, (false, false), None, Some("old"), None)]
+  #[case::reinstall(Action::Install, (false, true), Some(true), Some("hash"), None)]
+  #[case::satisfied(Action::Install, (false, false), Some(true), None, Some("already satisfied"))]
+  #[case::check_fails(Action::Install, (false, false), Some(false), Some("hash"), None)]
+  #[case::dry_run_skips_check(Action::Install, (true, false), Some(true), None, None)]
+  #[case::dry_run_installed(Action::Install, (true, false), Some(false), Some("hash"), Some("already installed"))]
+  #[case::uninstall_not_installed(Action::Uninstall, (false, false), Some(false), Some("hash"), Some("not installed"))]
+  #[case::uninstall_installed(Action::Uninstall, (false, false), Some(true), None, None)]
+  #[case::uninstall_dry_run(Action::Uninstall, (true, false), Some(false), None, None)]
+  #[case::uninstall_without_check(Action::Uninstall, (false, false), None, None, None)]
+  #[case::update_not_installed(Action::Update, (false, false), Some(false), None, Some("not installed"))]
+  #[case::update_installed(Action::Update, (false, false), Some(true), None, None)]
+  fn skip(#[case] action: Action, #[case] (dry_run, reinstall): (bool, bool), #[case] check: Option<bool>, #[case] installed: Option<&str>, #[case] expected: Option<&str>) {
+    let job = Job {
+      name: "/a".to_owned(),
+      cmd: "true".to_owned(),
+      args: vec!["true".to_owned()],
+      inner_cmd: "true".to_owned(),
+      hash: "hash".to_owned(),
+      check: Check {
+        cmd: check.map(|check| vec![check.to_string()]),
+        binary: None,
+      },
+    };
+
+    let mut state = state::State::default();
+    if let Some(hash) = installed {
+      state.installed.0.insert(
+        "/a".to_owned(),
+        state::Install {
+          hash: hash.to_owned(),
+          time: 0,
+          success: true,
+          rotz_version: String::new(),
+        },
+      );
+    }
+
+    let options = Options {
+      continue_on_error: false,
+      reinstall,
+      jobs: 1,
+    };
+
+    assert_that!(skip_reason(&job, action, &state, (&Globals { dry_run }, &options))).is_equal_to(expected);
   }
 }
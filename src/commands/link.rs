@@ -8,18 +8,20 @@ use std::{
 use crossterm::style::{Attribute, Stylize};
 use itertools::Itertools;
 use miette::{Diagnostic, Report, Result};
+use similar::TextDiff;
 use tap::Pipe;
 #[cfg(feature = "profiling")]
 use tracing::instrument;
-use velcro::hash_map;
+use walkdir::WalkDir;
 use wax::Pattern;
 
 use super::Command;
 use crate::{
   config::{Config, LinkType},
+  dot::LinkOptions,
   helpers,
   state::{self},
-  templating,
+  templating::{self, Parameters},
 };
 
 #[derive(thiserror::Error, Diagnostic, Debug)]
@@ -33,10 +35,75 @@ enum Error {
   #[diagnostic(code(link::orphan::remove))]
   RemovingOrphan(PathBuf, PathBuf, #[source] std::io::Error),
 
+  #[error("The dots {first_dot} and {second_dot} link to the same target \"{to}\"")]
+  #[diagnostic(
+    code(link::conflict),
+    help("\"{}\" of {} and \"{}\" of {} are both linked to this target", first_source.display(), first_dot, second_source.display(), second_dot)
+  )]
+  Conflict {
+    to: PathBuf,
+    first_dot: String,
+    first_source: PathBuf,
+    second_dot: String,
+    second_source: PathBuf,
+  },
+
   #[error("The file \"{0}\" already exists")]
   #[diagnostic(code(link::already_exists), help("Try using the --force flag"))]
   AlreadyExists(PathBuf),
 
+  #[error("The file \"{0}\" was modified since rotz created it")]
+  #[diagnostic(code(link::modified), help("Try using the --force flag to overwrite the changes"))]
+  Modified(PathBuf),
+
+  #[error("Could not hash the content of \"{0}\"")]
+  #[diagnostic(code(link::hash))]
+  Hashing(PathBuf, #[source] std::io::Error),
+
+  #[error("Could not back up \"{0}\"")]
+  #[diagnostic(code(link::backup))]
+  BackingUp(PathBuf, #[source] std::io::Error),
+
+  #[error("Could not read \"{0}\"")]
+  #[diagnostic(code(link::read))]
+  Reading(PathBuf, #[source] std::io::Error),
+
+  #[error("Could not render template \"{0}\"")]
+  #[diagnostic(code(link::render))]
+  RenderingTemplate(
+    PathBuf,
+    #[source]
+    #[diagnostic_source]
+    templating::Error,
+  ),
+
+  #[cfg(unix)]
+  #[error("Could not set the permissions of \"{0}\"")]
+  #[diagnostic(code(link::permissions))]
+  Permissions(PathBuf, #[source] std::io::Error),
+
+  #[cfg(unix)]
+  #[error("The user \"{0}\" does not exist")]
+  #[diagnostic(code(link::owner), help("Use the name of an existing user or a numeric user id"))]
+  UnknownOwner(String),
+
+  #[cfg(unix)]
+  #[error("The group \"{0}\" does not exist")]
+  #[diagnostic(code(link::group), help("Use the name of an existing group or a numeric group id"))]
+  UnknownGroup(String),
+
+  #[error("Could not record \"{0}\" in the undo journal")]
+  #[diagnostic(code(link::journal))]
+  Journal(PathBuf, #[source] std::io::Error),
+
+  #[error(transparent)]
+  #[diagnostic(transparent)]
+  State(state::Error),
+
+  #[error("Could not undo the change to \"{0}\"")]
+  #[diagnostic(code(link::rollback), help("The previous file may be left next to it as .{{name}}.rotz-{{pid}}-undo"))]
+  RollingBack(PathBuf, #[source] std::io::Error),
+
   #[error("The link source file \"{0}\" does not exist exists")]
   #[diagnostic(code(link::does_not_exist), help("Maybe you have a typo in the filename?"))]
   LinkSourceDoesNotExist(PathBuf),
@@ -57,145 +124,896 @@ impl<'a> Link<'a> {
   pub const fn new(config: crate::config::Config, engine: templating::Engine<'a>) -> Self {
     Self { config, engine }
   }
+
+  /// Checks that all orphans can be removed and all links can be created without changing the filesystem
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn plan(&self, links: &[DotLinks], orphans: &[(String, PathBuf, state::Link)], state: &state::State, force: bool) -> Result<(), helpers::MultipleErrors> {
+    let mut errors = Vec::new();
+
+    for (_, to, link) in orphans {
+      if link.target_hash.as_ref().is_some_and(|hash| !force && is_modified(to, hash)) {
+        errors.push(Error::Modified(to.clone()));
+      }
+    }
+
+    for (name, link) in links {
+      let base_path = self.config.dotfiles.join(&name[1..]);
+      for (from, tos) in link {
+        let from = base_path.join(from);
+        for to in tos.keys().map(helpers::resolve_home) {
+          let previous = state.linked.0.get(name).and_then(|l| l.get(&to));
+          if !from.exists() {
+            errors.push(Error::LinkSourceDoesNotExist(from.clone()));
+          } else if previous.and_then(|p| p.target_hash.as_ref()).is_some_and(|hash| !force && is_modified(&to, hash)) {
+            errors.push(Error::Modified(to));
+          } else if !force && previous.is_none() && fs::symlink_metadata(&to).is_ok() {
+            errors.push(Error::AlreadyExists(to));
+          }
+        }
+      }
+    }
+
+    helpers::join_err(errors)
+  }
 }
 
-impl<'a> Command for Link<'a> {
-  type Args = (crate::cli::Globals, crate::cli::Link, &'a state::Linked);
-  type Result = Result<state::Linked>;
+impl Command for Link<'_> {
+  type Args = (crate::cli::Globals, crate::cli::Link, state::State);
+  type Result = Result<state::State>;
 
   #[cfg_attr(feature = "profiling", instrument)]
-  fn execute(&self, (globals, link_command, linked): Self::Args) -> Self::Result {
+  fn execute(&self, (globals, link_command, mut state): Self::Args) -> Self::Result {
     let links = crate::dot::read_dots(&self.config.dotfiles, &link_command.dots, &self.config, &self.engine)?
       .into_iter()
       .filter_map(|d| d.1.links.map(|l| (d.0, l)))
-      .collect_vec();
+      .collect_vec()
+      .pipe(|links| unfold_links(&self.config.dotfiles, links))?;
+
+    check_conflicts(&self.config.dotfiles, &links)?;
+
+    let orphans = find_orphans(&state, &link_command.dots, &links)?;
+
+    if link_command.transactional {
+      self.plan(&links, &orphans, &state, link_command.force)?;
+    }
+
+    let mut journal = (link_command.transactional && !globals.dry_run).then(Journal::default);
+
+    if let Err(err) = remove_orphans(orphans, &mut state, journal.as_mut(), link_command.force, globals.dry_run) {
+      if let Some(journal) = journal {
+        journal.rollback();
+      }
+      return Err(err);
+    }
 
-    {
-      let current_links = links.iter().flat_map(|l| l.1.iter().map(|h| h.1.iter())).flatten().map(helpers::resolve_home).collect::<HashSet<_>>();
+    for (name, link) in links {
+      println!("{}Linking {}{}\n", Attribute::Bold, name.as_str().dark_blue(), Attribute::Reset);
 
-      let mut errors = Vec::new();
+      let base_path = self.config.dotfiles.join(&name[1..]);
+      let parameters = Parameters { config: &self.config, name: &name };
+      for (from, tos) in link {
+        for (mut to, options) in tos {
+          println!("  {} -> {}", from.to_string_lossy().dark_green(), to.to_string_lossy().dark_green());
+          let from = base_path.join(&from);
+          to = helpers::resolve_home(&to);
 
-      let dots = helpers::glob_from_vec(&link_command.dots, None)?;
-      let linked = linked.0.iter().filter(|l| dots.is_match(l.0.as_str()));
+          let link_type = options.link_type.as_ref().unwrap_or(&self.config.link_type);
+          let relative = options.relative.unwrap_or(self.config.relative_links);
+          let read = |path: &Path| read_dotfile(&self.engine, path, link_type, &parameters);
 
-      for (name, links) in linked {
-        let mut printed = false;
-        for (to, from) in links {
-          if !current_links.contains(to) {
-            let mut removed = true;
-            if !globals.dry_run {
-              if let Err(err) = fs::remove_file(to) {
-                removed = false;
+          if !globals.dry_run {
+            let created_dirs = missing_parents(&to);
+            let previous = state.linked.0.get(&name).and_then(|l| l.get(&to)).cloned();
 
-                if err.kind() != std::io::ErrorKind::NotFound {
-                  errors.push(Error::RemovingOrphan(from.clone(), to.clone(), err));
+            let result = state::check_interrupted()
+              .map_err(Error::State)
+              .and_then(|()| journal.as_mut().map_or(Ok(()), |journal| journal.record(&to, &created_dirs, previous.as_ref())))
+              .and_then(|()| create_link(&from, &to, (link_type, relative, &options), &created_dirs, link_command.force, previous.as_ref(), &read));
+
+            let Created { hash, backup, replaced } = match result {
+              Ok(created) => created,
+              Err(err) => {
+                if let Some(journal) = journal {
+                  journal.rollback();
+                  return Err(err.into());
+                }
+                if matches!(err, Error::State(_)) {
+                  return Err(err.into());
                 }
+                eprintln!("\n Error: {:?}", Report::new(err));
+                continue;
               }
-            }
+            };
 
-            if removed {
-              if !printed {
-                println!("{}Removing orphans for {}{}\n", Attribute::Bold, name.as_str().dark_blue(), Attribute::Reset);
-                printed = true;
+            if let Some(backup) = backup {
+              println!("    {} {}", "backed up existing file to".dark_yellow(), backup.to_string_lossy().dark_green());
+              if let Some(journal) = journal.as_mut() {
+                journal.backed_up(&to, &backup);
               }
-              println!("  x {}", to.to_string_lossy().dark_green());
+              state.backups.push(to.clone(), backup);
+            }
+
+            let link = state_link(&from, link_type, previous.as_ref(), hash, replaced);
+            state.linked.0.entry(name.clone()).or_default().insert(to.clone(), link);
+            if journal.is_none() {
+              state.commit()?;
             }
           }
         }
+      }
 
-        if printed {
-          println!();
+      println!();
+    }
+
+    if let Some(journal) = journal {
+      journal.commit();
+    }
+
+    state.pipe(Ok)
+  }
+}
+
+/// Reads the content of a dotfile and renders it if it is linked as a template
+fn read_dotfile(engine: &templating::Engine<'_>, path: &Path, link_type: &LinkType, parameters: &Parameters<'_>) -> std::result::Result<Vec<u8>, Error> {
+  let content = fs::read(path).map_err(|e| Error::Reading(path.to_path_buf(), e))?;
+  if !link_type.is_template() {
+    return content.pipe(Ok);
+  }
+
+  let template = String::from_utf8(content).map_err(|e| Error::Reading(path.to_path_buf(), std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
+  engine
+    .render(&template, parameters)
+    .map(String::into_bytes)
+    .map_err(|e| Error::RenderingTemplate(path.to_path_buf(), e))
+}
+
+/// Content and permissions a copied or rendered target of `from` is written with, none if the dotfile cannot be read or rendered
+pub(crate) fn expected_contents(engine: &templating::Engine<'_>, from: &Path, link_type: &LinkType, options: &LinkOptions, parameters: &Parameters<'_>) -> Option<Contents> {
+  let mut contents = read_contents(from, &|path| read_dotfile(engine, path, link_type, parameters)).ok()?;
+  apply_modes(&mut contents, options);
+  contents.pipe(Some)
+}
+
+/// Reads the content a copied or templated file gets written with
+type Read<'a> = &'a dyn Fn(&Path) -> std::result::Result<Vec<u8>, Error>;
+
+/// Content and permissions of a file or of all files in a directory keyed by their relative path, directories have no content
+pub(crate) type Contents = Vec<(PathBuf, Option<Vec<u8>>, fs::Permissions)>;
+
+/// Links of a dot keyed by their source, each with the targets and their options
+type DotLinks = (String, HashMap<PathBuf, HashMap<PathBuf, LinkOptions>>);
+
+/// Replaces links of unfolded directories with a link for every file in the directory
+#[cfg_attr(feature = "profiling", instrument)]
+pub(crate) fn unfold_links(dotfiles: &Path, links: Vec<DotLinks>) -> Result<Vec<DotLinks>> {
+  let mut unfolded_links = Vec::new();
+
+  for (name, link) in links {
+    let base_path = dotfiles.join(&name[1..]);
+    let mut unfolded = HashMap::<PathBuf, HashMap<PathBuf, LinkOptions>>::new();
+
+    for (from, tos) in link {
+      for (to, options) in tos {
+        let source = base_path.join(&from);
+        if !options.unfold || !source.is_dir() {
+          unfolded.entry(from.clone()).or_default().insert(to, options);
+          continue;
+        }
+
+        let ignore = helpers::glob_from_vec(&options.ignore, None)?;
+        let options = LinkOptions {
+          unfold: false,
+          ignore: Vec::new(),
+          ..options
+        };
+
+        let entries = WalkDir::new(&source)
+          .sort_by_file_name()
+          .into_iter()
+          .filter_entry(|e| e.path().strip_prefix(&source).map_or(true, |relative| !ignore.is_match(relative)));
+
+        for entry in entries {
+          let entry = entry.map_err(|e| Error::Reading(source.clone(), e.into()))?;
+          if entry.file_type().is_dir() {
+            continue;
+          }
+
+          let relative = entry.path().strip_prefix(&source).map_err(|e| Error::Reading(entry.path().to_path_buf(), std::io::Error::other(e)))?;
+          unfolded.entry(from.join(relative)).or_default().insert(to.join(relative), options.clone());
         }
       }
+    }
+
+    unfolded_links.push((name, unfolded));
+  }
+
+  unfolded_links.pipe(Ok)
+}
 
-      helpers::join_err(errors)?;
+/// Checks that no two links of the selected dots share the same target
+#[cfg_attr(feature = "profiling", instrument)]
+fn check_conflicts(dotfiles: &Path, links: &[DotLinks]) -> Result<(), helpers::MultipleErrors> {
+  let mut targets = HashMap::<PathBuf, (&str, PathBuf)>::new();
+  let mut errors = Vec::new();
+
+  for (name, link) in links {
+    let base_path = dotfiles.join(&name[1..]);
+    for (from, tos) in link.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+      let from = base_path.join(from);
+      for to in tos.keys().map(helpers::resolve_home).sorted() {
+        match targets.get(&to) {
+          Some((first_dot, first_source)) if *first_dot != name || *first_source != from => errors.push(Error::Conflict {
+            to,
+            first_dot: (*first_dot).to_owned(),
+            first_source: first_source.clone(),
+            second_dot: name.clone(),
+            second_source: from.clone(),
+          }),
+          Some(_) => {}
+          None => {
+            targets.insert(to, (name, from.clone()));
+          }
+        }
+      }
     }
+  }
 
-    let mut new_linked = hash_map!();
+  helpers::join_err(errors)
+}
 
-    for (name, link) in links {
-      println!("{}Linking {}{}\n", Attribute::Bold, name.as_str().dark_blue(), Attribute::Reset);
+/// Removes the targets of links which are not defined in the dotfiles anymore, moving them into the journal if there is one
+#[cfg_attr(feature = "profiling", instrument)]
+/// Links recorded for the selected `dots` which are not part of `links` anymore, the links of other dots are kept
+#[cfg_attr(feature = "profiling", instrument)]
+fn find_orphans(state: &state::State, dots: &[String], links: &[DotLinks]) -> Result<Vec<(String, PathBuf, state::Link)>> {
+  let current_links = links.iter().flat_map(|l| l.1.iter().map(|h| h.1.keys())).flatten().map(helpers::resolve_home).collect::<HashSet<_>>();
+  let dots = helpers::glob_from_vec(dots, None)?;
 
-      let mut new_linked_inner = hash_map!();
+  state
+    .linked
+    .0
+    .iter()
+    .filter(|l| dots.is_match(l.0.as_str()))
+    .flat_map(|(name, links)| links.iter().filter(|l| !current_links.contains(l.0)).map(move |(to, link)| (name.clone(), to.clone(), link.clone())))
+    .collect_vec()
+    .pipe(Ok)
+}
 
-      let base_path = self.config.dotfiles.join(&name[1..]);
-      for (from, tos) in link {
-        for mut to in tos {
-          println!("  {} -> {}", from.to_string_lossy().dark_green(), to.to_string_lossy().dark_green());
-          let from = base_path.join(&from);
-          to = helpers::resolve_home(&to);
+fn remove_orphans(orphans: Vec<(String, PathBuf, state::Link)>, state: &mut state::State, mut journal: Option<&mut Journal>, force: bool, dry_run: bool) -> Result<()> {
+  let mut errors = Vec::new();
+  let mut printed = None;
 
-          if !globals.dry_run {
-            if let Err(err) = create_link(&from, &to, &self.config.link_type, link_command.force, linked.0.get(&name)) {
-              eprintln!("\n Error: {:?}", Report::new(err));
-            } else {
-              new_linked_inner.insert(to.clone(), from.clone());
-            }
-          }
+  for (name, to, link) in orphans {
+    state::check_interrupted()?;
+
+    let hash = link.target_hash.as_ref();
+    if hash.is_some_and(|hash| !force && is_modified(&to, hash)) {
+      errors.push(Error::Modified(to));
+      continue;
+    }
+
+    let mut removed = true;
+    if !dry_run {
+      let result = match journal.as_deref_mut() {
+        Some(journal) => journal.remove(&to),
+        None if hash.is_some() => remove_target(&to),
+        None => fs::remove_file(&to),
+      };
+
+      if let Err(err) = result {
+        removed = false;
+
+        if err.kind() != std::io::ErrorKind::NotFound {
+          errors.push(Error::RemovingOrphan(link.source.clone(), to.clone(), err));
         }
       }
+    }
 
-      if !new_linked_inner.is_empty() {
-        new_linked.insert(name, new_linked_inner);
+    if removed {
+      if printed.as_ref() != Some(&name) {
+        if printed.is_some() {
+          println!();
+        }
+        println!("{}Removing orphans for {}{}\n", Attribute::Bold, name.as_str().dark_blue(), Attribute::Reset);
       }
+      println!("  x {}", to.to_string_lossy().dark_green());
 
-      println!();
+      if !dry_run {
+        state.linked.remove(&name, &to);
+        if journal.is_none() {
+          state.commit()?;
+        }
+      }
+
+      printed = Some(name);
     }
+  }
 
-    state::Linked(new_linked).pipe(Ok)
+  if printed.is_some() {
+    println!();
   }
+
+  helpers::join_err(errors)?;
+
+  ().pipe(Ok)
 }
 
-#[cfg_attr(feature = "profiling", instrument)]
-fn create_link(from: &Path, to: &Path, link_type: &LinkType, force: bool, linked: Option<&HashMap<PathBuf, PathBuf>>) -> std::result::Result<(), Error> {
+/// Result of creating a link
+#[derive(Debug, Default)]
+struct Created {
+  /// Hash of the content written to the target
+  hash: Option<String>,
+  /// Backup location of the file which previously existed at the target
+  backup: Option<PathBuf>,
+  /// Whether the target was created or replaced instead of being up to date
+  replaced: bool,
+}
+
+#[cfg_attr(feature = "profiling", instrument(skip(read)))]
+fn create_link(
+  from: &Path,
+  to: &Path,
+  (link_type, relative, options): (&LinkType, bool, &LinkOptions),
+  created_dirs: &[PathBuf],
+  force: bool,
+  previous: Option<&state::Link>,
+  read: Read<'_>,
+) -> std::result::Result<Created, Error> {
+  let hash = previous.and_then(|p| p.target_hash.as_ref());
+
   if !from.exists() {
     return Error::LinkSourceDoesNotExist(from.to_path_buf()).pipe(Err);
   }
 
-  let create: fn(&Path, &Path) -> std::result::Result<(), std::io::Error> = if link_type.is_symbolic() { symlink } else { hardlink };
+  if hash.is_some_and(|hash| !force && is_modified(to, hash)) {
+    if let Ok(contents) = read_contents(from, read) {
+      print_diff(from, to, &contents);
+    }
+    return Error::Modified(to.to_path_buf()).pipe(Err);
+  }
+
+  let contents = if link_type.is_copy() || link_type.is_template() {
+    let mut contents = read_contents(from, read)?;
+    apply_modes(&mut contents, options);
+    contents.pipe(Some)
+  } else {
+    None
+  };
+
+  let mut created = Created::default();
+  let mut replace_existing = true;
+  let mut back_up = false;
+
+  if fs::symlink_metadata(to).is_ok() {
+    if !force && previous.is_none() {
+      return Error::AlreadyExists(to.to_path_buf()).pipe(Err);
+    }
+
+    let managed = is_managed(to, previous);
+    let up_to_date = contents.as_ref().map_or_else(
+      || links_to(from, to) && (!link_type.is_symbolic() || fs::read_link(to).is_ok_and(|source| source.is_relative() == relative)),
+      |contents| !links_to(from, to) && is_written(to, contents),
+    );
+
+    if !managed {
+      back_up = true;
+    } else if up_to_date {
+      replace_existing = false;
+    } else if hash.is_some() {
+      println!("    {}", "source changed, updating target".dark_yellow());
+    }
+  }
+
+  if replace_existing {
+    created.replaced = true;
+    let temporary = temporary_path(to, "new");
+    if fs::symlink_metadata(&temporary).is_ok() {
+      remove_target(&temporary).map_err(|e| Error::Symlink(from.to_path_buf(), to.to_path_buf(), e))?;
+    }
+
+    contents
+      .as_ref()
+      .map_or_else(
+        || {
+          if link_type.is_symbolic() {
+            symlink(from, &temporary, relative)
+          } else {
+            hardlink(from, &temporary)
+          }
+        },
+        |contents| write_contents(&temporary, contents),
+      )
+      .map_err(|e| {
+        remove_target(&temporary).ok();
+        Error::Symlink(from.to_path_buf(), to.to_path_buf(), e)
+      })?;
+
+    apply_permissions(from, &temporary, link_type, options, created_dirs).inspect_err(|_| {
+      remove_target(&temporary).ok();
+      for dir in created_dirs {
+        fs::remove_dir(dir).ok();
+      }
+    })?;
+
+    if back_up {
+      created.backup = state::backups::create(to)
+        .map_err(|e| {
+          remove_target(&temporary).ok();
+          Error::BackingUp(to.to_path_buf(), e)
+        })?
+        .pipe(Some);
+    }
+
+    if let Err(err) = replace(&temporary, to) {
+      if let Some(backup) = &created.backup {
+        state::backups::restore(backup, to).ok();
+      }
+      return Error::Symlink(from.to_path_buf(), to.to_path_buf(), err).pipe(Err);
+    }
+  } else {
+    apply_permissions(from, to, link_type, options, created_dirs)?;
+  }
+
+  if contents.is_some() {
+    created.hash = helpers::hash_path(to).map_err(|e| Error::Hashing(to.to_path_buf(), e))?.pipe(Some);
+  }
+
+  created.pipe(Ok)
+}
+
+/// Builds the state entry of a created link, an unchanged target keeps when and by which version it was created
+fn state_link(from: &Path, link_type: &LinkType, previous: Option<&state::Link>, hash: Option<String>, replaced: bool) -> state::Link {
+  let (created, rotz_version) = previous
+    .filter(|_| !replaced)
+    .map_or_else(|| (state::timestamp(), Some(env!("CARGO_PKG_VERSION").to_owned())), |p| (p.created, p.rotz_version.clone()));
+
+  state::Link {
+    source: from.to_path_buf(),
+    link_type: link_type.clone(),
+    created,
+    source_hash: helpers::hash_path(from).ok(),
+    target_hash: hash,
+    rotz_version,
+  }
+}
+
+/// Path next to `to` under which a new target is prepared before it is moved into place
+fn temporary_path(to: &Path, suffix: &str) -> PathBuf {
+  to.with_file_name(format!(".{}.rotz-{}-{suffix}", to.file_name().unwrap_or_default().to_string_lossy(), std::process::id()))
+}
 
-  match create(from, to) {
-    Ok(ok) => ok.pipe(Ok),
-    Err(err) => match err.kind() {
-      std::io::ErrorKind::AlreadyExists => {
-        if force || linked.is_some_and(|l| l.contains_key(to)) {
-          if to.is_dir() { fs::remove_dir_all(to) } else { fs::remove_file(to) }.map_err(|e| Error::Symlink(from.to_path_buf(), to.to_path_buf(), e))?;
-          create(from, to)
-        } else {
-          return Error::AlreadyExists(to.to_path_buf()).pipe(Err);
+/// Moves a prepared target into place. The existing target is replaced atomically where the platform allows it
+/// and otherwise moved aside first and moved back if the replacement fails.
+#[cfg_attr(feature = "profiling", instrument)]
+fn replace(temporary: &Path, to: &Path) -> std::io::Result<()> {
+  let result = match fs::rename(temporary, to) {
+    Err(_) if fs::symlink_metadata(to).is_ok() => {
+      let old = temporary_path(to, "old");
+      fs::rename(to, &old).and_then(|()| {
+        if let Err(err) = fs::rename(temporary, to) {
+          fs::rename(&old, to)?;
+          return Err(err);
         }
+        remove_target(&old)
+      })
+    }
+    result => result,
+  };
+
+  if result.is_err() {
+    remove_target(temporary).ok();
+  }
+
+  result
+}
+
+/// Directories which do not exist yet and will be created to place `to`
+fn missing_parents(to: &Path) -> Vec<PathBuf> {
+  to.ancestors()
+    .skip(1)
+    .take_while(|p| !p.as_os_str().is_empty() && fs::symlink_metadata(p).is_err())
+    .map(Path::to_path_buf)
+    .collect()
+}
+
+/// Applies the ownership of a link to its written target and the mode and ownership to the directories created for it
+///
+/// The modes of written targets are set when their content is written
+#[cfg(unix)]
+#[cfg_attr(feature = "profiling", instrument)]
+fn apply_permissions(from: &Path, to: &Path, link_type: &LinkType, options: &LinkOptions, created_dirs: &[PathBuf]) -> std::result::Result<(), Error> {
+  use std::os::unix::fs::{PermissionsExt, chown};
+
+  use nix::unistd::{Group, User};
+
+  let owner = options
+    .owner
+    .as_deref()
+    .map(|o| {
+      o.parse()
+        .ok()
+        .or_else(|| User::from_name(o).ok().flatten().map(|user| user.uid.as_raw()))
+        .ok_or_else(|| Error::UnknownOwner(o.to_owned()))
+    })
+    .transpose()?;
+  let group = options
+    .group
+    .as_deref()
+    .map(|g| {
+      g.parse()
+        .ok()
+        .or_else(|| Group::from_name(g).ok().flatten().map(|group| group.gid.as_raw()))
+        .ok_or_else(|| Error::UnknownGroup(g.to_owned()))
+    })
+    .transpose()?;
+
+  let apply = |path: &Path, mode: Option<crate::dot::FileMode>| -> std::result::Result<(), Error> {
+    if let Some(mode) = mode {
+      fs::set_permissions(path, fs::Permissions::from_mode(mode.0)).map_err(|e| Error::Permissions(path.to_path_buf(), e))?;
+    }
+    if owner.is_some() || group.is_some() {
+      chown(path, owner, group).map_err(|e| Error::Permissions(path.to_path_buf(), e))?;
+    }
+    ().pipe(Ok)
+  };
+
+  if link_type.is_copy() || link_type.is_template() {
+    if owner.is_some() || group.is_some() {
+      for entry in WalkDir::new(to).contents_first(true) {
+        let entry = entry.map_err(|e| Error::Permissions(to.to_path_buf(), e.into()))?;
+        apply(entry.path(), None)?;
       }
-      _ => err.pipe(Err),
-    },
+    }
+  } else if let Some(mode) = options.mode {
+    warn_loose_permissions(from, mode);
+  }
+
+  for dir in created_dirs {
+    apply(dir, options.dir_mode)?;
+  }
+
+  ().pipe(Ok)
+}
+
+#[cfg(windows)]
+#[allow(clippy::unnecessary_wraps)]
+const fn apply_permissions(_: &Path, _: &Path, _: &LinkType, _: &LinkOptions, _: &[PathBuf]) -> std::result::Result<(), Error> {
+  Ok(())
+}
+
+/// Replaces the permissions copied from the source with the modes of the link options
+#[cfg(unix)]
+fn apply_modes(contents: &mut Contents, options: &LinkOptions) {
+  use std::os::unix::fs::PermissionsExt;
+
+  for (_, content, permissions) in contents {
+    if let Some(mode) = if content.is_some() { options.mode } else { options.dir_mode } {
+      *permissions = fs::Permissions::from_mode(mode.0);
+    }
+  }
+}
+
+#[cfg(windows)]
+const fn apply_modes(_: &mut Contents, _: &LinkOptions) {}
+
+/// Links share the permissions of their source so only a warning can be given if they are looser than requested
+#[cfg(unix)]
+fn warn_loose_permissions(from: &Path, mode: crate::dot::FileMode) {
+  use std::os::unix::fs::PermissionsExt;
+
+  for entry in WalkDir::new(from).into_iter().flatten() {
+    let Ok(metadata) = entry.metadata() else {
+      continue;
+    };
+
+    let current = metadata.permissions().mode() & 0o7777;
+    if !metadata.is_dir() && current & !mode.0 != 0 {
+      println!(
+        "    {} {} {}",
+        entry.path().to_string_lossy().dark_green(),
+        format!("has the mode {current:o} which is looser than").dark_yellow(),
+        format!("{:o}", mode.0).dark_green()
+      );
+    }
+  }
+}
+
+/// Change made by a transactional link run which is undone if the run fails
+#[derive(Debug)]
+enum Undo {
+  /// The target did not exist before
+  Created(PathBuf),
+  /// The previous target was kept at `saved`
+  Saved { target: PathBuf, saved: PathBuf },
+  /// The directory was created for a target
+  Directory(PathBuf),
+  /// The previous target was moved into the backup store
+  BackedUp { target: PathBuf, backup: PathBuf },
+}
+
+/// Undo journal of a transactional link run
+#[derive(Debug, Default)]
+struct Journal(Vec<Undo>);
+
+impl Journal {
+  /// Moves an orphaned target aside instead of removing it
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn remove(&mut self, to: &Path) -> std::io::Result<()> {
+    let saved = temporary_path(to, "undo");
+    fs::rename(to, &saved)?;
+    self.0.push(Undo::Saved { target: to.to_path_buf(), saved });
+
+    ().pipe(Ok)
+  }
+
+  /// Keeps a copy of the target before a link is created at it
+  ///
+  /// Targets which are not managed by rotz are moved into the backup store as they are and are recorded once that happened
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn record(&mut self, to: &Path, created_dirs: &[PathBuf], previous: Option<&state::Link>) -> std::result::Result<(), Error> {
+    self.0.extend(created_dirs.iter().rev().cloned().map(Undo::Directory));
+
+    let Ok(metadata) = fs::symlink_metadata(to) else {
+      self.0.push(Undo::Created(to.to_path_buf()));
+      return ().pipe(Ok);
+    };
+
+    if !is_managed(to, previous) {
+      return ().pipe(Ok);
+    }
+
+    let saved = temporary_path(to, "undo");
+    if fs::symlink_metadata(&saved).is_ok() {
+      remove_target(&saved).map_err(|e| Error::Journal(to.to_path_buf(), e))?;
+    }
+
+    if metadata.is_symlink() {
+      fs::read_link(to).and_then(|source| symlink(&source, &saved, false))
+    } else if metadata.is_dir() {
+      read_contents(to, &|path| fs::read(path).map_err(|e| Error::Reading(path.to_path_buf(), e)))
+        .map_err(std::io::Error::other)
+        .and_then(|contents| write_contents(&saved, &contents))
+    } else {
+      fs::hard_link(to, &saved)
+    }
+    .map_err(|e| Error::Journal(to.to_path_buf(), e))?;
+
+    self.0.push(Undo::Saved { target: to.to_path_buf(), saved });
+
+    ().pipe(Ok)
+  }
+
+  fn backed_up(&mut self, target: &Path, backup: &Path) {
+    self.0.push(Undo::BackedUp {
+      target: target.to_path_buf(),
+      backup: backup.to_path_buf(),
+    });
+  }
+
+  /// Removes the copies of the previous targets
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn commit(self) {
+    for undo in self.0 {
+      if let Undo::Saved { saved, .. } = undo {
+        remove_target(&saved).ok();
+      }
+    }
+  }
+
+  /// Undoes all changes in reverse order
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn rollback(self) {
+    println!("{}Rolling back{}\n", Attribute::Bold, Attribute::Reset);
+
+    for undo in self.0.into_iter().rev() {
+      let (path, result) = match undo {
+        Undo::Created(target) => {
+          let result = if fs::symlink_metadata(&target).is_ok() { remove_target(&target) } else { Ok(()) };
+          (target, result)
+        }
+        Undo::Saved { target, saved } => {
+          let result = if fs::symlink_metadata(&target).is_ok() { remove_target(&target) } else { Ok(()) }.and_then(|()| fs::rename(&saved, &target));
+          (target, result)
+        }
+        Undo::Directory(directory) => {
+          fs::remove_dir(&directory).ok();
+          continue;
+        }
+        Undo::BackedUp { target, backup } => {
+          let result = if fs::symlink_metadata(&target).is_ok() { remove_target(&target) } else { Ok(()) }.and_then(|()| state::backups::restore(&backup, &target));
+          (target, result)
+        }
+      };
+
+      match result {
+        Ok(()) => println!("  ~ {}", path.to_string_lossy().dark_green()),
+        Err(err) => eprintln!("\n Error: {:?}", Report::new(Error::RollingBack(path, err))),
+      }
+    }
+
+    println!();
+  }
+}
+
+/// Whether the existing target at `to` is the unchanged link rotz recorded as `previous`, other targets are backed up before they are replaced
+fn is_managed(to: &Path, previous: Option<&state::Link>) -> bool {
+  previous.is_some_and(|previous| previous.target_hash.as_ref().map_or_else(|| links_to(&previous.source, to), |hash| !is_modified(to, hash)))
+}
+
+pub(crate) fn remove_target(to: &Path) -> std::io::Result<()> {
+  if to.is_dir() { fs::remove_dir_all(to) } else { fs::remove_file(to) }
+}
+
+/// Checks whether the content of `to` differs from the content rotz wrote
+pub(crate) fn is_modified(to: &Path, hash: &str) -> bool {
+  fs::symlink_metadata(to).is_ok() && helpers::hash_path(to).map_or(true, |current| current != hash)
+}
+
+fn join_relative(base: &Path, relative: &Path) -> PathBuf {
+  if relative.as_os_str().is_empty() { base.to_path_buf() } else { base.join(relative) }
+}
+
+#[cfg_attr(feature = "profiling", instrument(skip(read)))]
+fn read_contents(from: &Path, read: Read<'_>) -> std::result::Result<Contents, Error> {
+  if !from.is_dir() {
+    let permissions = fs::metadata(from).map_err(|e| Error::Reading(from.to_path_buf(), e))?.permissions();
+    return vec![(PathBuf::new(), read(from)?.pipe(Some), permissions)].pipe(Ok);
+  }
+
+  let mut contents = Vec::new();
+  for entry in WalkDir::new(from).sort_by_file_name() {
+    let entry = entry.map_err(|e| Error::Reading(from.to_path_buf(), e.into()))?;
+    let relative = entry.path().strip_prefix(from).map_err(|e| Error::Reading(entry.path().to_path_buf(), std::io::Error::other(e)))?;
+    let permissions = entry.metadata().map_err(|e| Error::Reading(entry.path().to_path_buf(), e.into()))?.permissions();
+    let content = if entry.file_type().is_dir() { None } else { read(entry.path())?.pipe(Some) };
+    contents.push((relative.to_path_buf(), content, permissions));
+  }
+
+  contents.pipe(Ok)
+}
+
+/// Checks whether `to` has the content and permissions of `contents`
+pub(crate) fn is_written(to: &Path, contents: &Contents) -> bool {
+  !fs::symlink_metadata(to).is_ok_and(|m| m.is_symlink())
+    && contents.iter().all(|(relative, content, permissions)| {
+      let target = join_relative(to, relative);
+      content.as_ref().map_or_else(|| target.is_dir(), |content| fs::read(&target).is_ok_and(|c| &c == content)) && has_permissions(&target, permissions)
+    })
+}
+
+/// Checks whether `target` has the permission bits of `permissions`
+fn has_permissions(target: &Path, permissions: &fs::Permissions) -> bool {
+  #[cfg(unix)]
+  use std::os::unix::fs::PermissionsExt;
+
+  fs::metadata(target).is_ok_and(|metadata| {
+    #[cfg(unix)]
+    return metadata.permissions().mode() & 0o7777 == permissions.mode() & 0o7777;
+    #[cfg(windows)]
+    return metadata.permissions().readonly() == permissions.readonly();
+  })
+}
+
+#[cfg_attr(feature = "profiling", instrument(skip(contents)))]
+fn write_contents(to: &Path, contents: &Contents) -> std::io::Result<()> {
+  if let Some(parent) = to.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+
+  for (relative, content, _) in contents {
+    let target = join_relative(to, relative);
+    if let Some(content) = content {
+      write_private(&target, content)?;
+    } else {
+      fs::create_dir_all(target)?;
+    }
+  }
+
+  for (relative, _, permissions) in contents.iter().rev() {
+    fs::set_permissions(join_relative(to, relative), permissions.clone())?;
+  }
+
+  ().pipe(Ok)
+}
+
+/// Writes a file which only the current user can read until its permissions are set
+#[cfg(unix)]
+fn write_private(path: &Path, content: &[u8]) -> std::io::Result<()> {
+  use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+  fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?.write_all(content)
+}
+
+#[cfg(windows)]
+fn write_private(path: &Path, content: &[u8]) -> std::io::Result<()> {
+  fs::write(path, content)
+}
+
+#[cfg_attr(feature = "profiling", instrument(skip(contents)))]
+fn print_diff(from: &Path, to: &Path, contents: &Contents) {
+  println!();
+  for (relative, content, _) in contents {
+    let Some(content) = content else {
+      continue;
+    };
+
+    let target = join_relative(to, relative);
+    let current = fs::read(&target).ok();
+    if current.as_ref() == Some(content) {
+      continue;
+    }
+
+    match (current.map(String::from_utf8), std::str::from_utf8(content)) {
+      (Some(Ok(current)), Ok(content)) => {
+        for line in TextDiff::from_lines(content, &current)
+          .unified_diff()
+          .header(&join_relative(from, relative).to_string_lossy(), &target.to_string_lossy())
+          .to_string()
+          .lines()
+        {
+          match line.chars().next() {
+            Some('+') => println!("  {}", line.dark_green()),
+            Some('-') => println!("  {}", line.dark_red()),
+            _ => println!("  {line}"),
+          }
+        }
+      }
+      (None, _) => println!("  {} {}", target.to_string_lossy().dark_green(), "was removed".dark_yellow()),
+      _ => println!("  {} {}", target.to_string_lossy().dark_green(), "was modified".dark_yellow()),
+    }
+  }
+}
+
+/// Checks whether `to` is a link rotz could have created pointing to `from`
+#[cfg_attr(feature = "profiling", instrument)]
+pub(crate) fn links_to(from: &Path, to: &Path) -> bool {
+  let Ok(metadata) = fs::symlink_metadata(to) else {
+    return false;
+  };
+
+  if metadata.is_symlink() {
+    fs::read_link(to).is_ok_and(|source| source == from || (source.is_relative() && to.parent().is_some_and(|parent| same_file::is_same_file(parent.join(&source), from).unwrap_or(false))))
+  } else {
+    same_file::is_same_file(from, to).unwrap_or(false)
   }
-  .map_err(|e| Error::Symlink(from.to_path_buf(), to.to_path_buf(), e))
+}
+
+/// Source a symbolic link at `to` points to, relative to the directory of the link if `relative` is set
+fn link_source(from: &Path, to: &Path, relative: bool) -> std::io::Result<PathBuf> {
+  if !relative {
+    return from.to_path_buf().pipe(Ok);
+  }
+
+  let parent = to.parent().map_or_else(|| Path::new(".").canonicalize(), Path::canonicalize)?;
+  helpers::relative_path(&from.canonicalize()?, &parent).unwrap_or_else(|| from.to_path_buf()).pipe(Ok)
 }
 
 #[cfg(windows)]
 #[cfg_attr(feature = "profiling", instrument)]
-fn symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+fn symlink(from: &Path, to: &Path, relative: bool) -> std::io::Result<()> {
   use std::os::windows::fs;
 
   if let Some(parent) = to.parent() {
     std::fs::create_dir_all(parent)?;
   }
 
+  let source = link_source(from, to, relative)?;
   if from.is_dir() {
-    fs::symlink_dir(from, to)?;
+    fs::symlink_dir(source, to)?;
   } else {
-    fs::symlink_file(from, to)?;
+    fs::symlink_file(source, to)?;
   };
   ().pipe(Ok)
 }
 
 #[cfg(unix)]
 #[cfg_attr(feature = "profiling", instrument)]
-fn symlink(from: &Path, to: &Path) -> std::io::Result<()> {
+fn symlink(from: &Path, to: &Path, relative: bool) -> std::io::Result<()> {
   use std::os::unix::fs;
   if let Some(parent) = to.parent() {
     std::fs::create_dir_all(parent)?;
   }
-  fs::symlink(from, to)?;
+  fs::symlink(link_source(from, to, relative)?, to)?;
   ().pipe(Ok)
 }
 
@@ -223,3 +1041,262 @@ fn hardlink(from: &Path, to: &Path) -> std::io::Result<()> {
   fs::hard_link(from, to)?;
   ().pipe(Ok)
 }
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    fs,
+    path::{Path, PathBuf},
+  };
+
+  use speculoos::prelude::*;
+  use velcro::hash_map;
+
+  #[cfg(unix)]
+  use super::apply_modes;
+  use super::{Error, Journal, check_conflicts, create_link, find_orphans, missing_parents, remove_orphans};
+  use crate::{
+    config::LinkType,
+    dot::{FileMode, LinkOptions},
+    state,
+  };
+
+  /// Links `from` to `to` as a copy after recording it in `journal`
+  fn copy(journal: &mut Journal, from: &Path, to: &Path, previous: Option<&state::Link>) {
+    let created_dirs = missing_parents(to);
+    journal.record(to, &created_dirs, previous).unwrap();
+    create_link(from, to, (&LinkType::Copy, false, &LinkOptions::default()), &created_dirs, true, previous, &|path| {
+      fs::read(path).map_err(|e| Error::Reading(path.to_path_buf(), e))
+    })
+    .unwrap();
+  }
+
+  /// Paths rotz left next to the targets in `dir`
+  fn leftovers(dir: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(dir)
+      .into_iter()
+      .flatten()
+      .filter(|e| e.file_name().to_string_lossy().contains(".rotz-"))
+      .map(walkdir::DirEntry::into_path)
+      .collect()
+  }
+
+  #[test]
+  fn rollback_restores_previous_targets() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    let existing = dir.path().join("existing");
+    let created = dir.path().join("new").join("nested").join("created");
+    fs::write(&source, "source").unwrap();
+    fs::write(&existing, "existing").unwrap();
+
+    let previous = state::Link {
+      source: source.clone(),
+      link_type: LinkType::Copy,
+      created: 0,
+      source_hash: None,
+      target_hash: crate::helpers::hash_path(&existing).ok(),
+      rotz_version: None,
+    };
+
+    let mut journal = Journal::default();
+    copy(&mut journal, &source, &existing, Some(&previous));
+    copy(&mut journal, &source, &created, None);
+
+    assert_that!(fs::read_to_string(&existing).unwrap()).is_equal_to("source".to_owned());
+    assert_that!(fs::read_to_string(&created).unwrap()).is_equal_to("source".to_owned());
+
+    journal.rollback();
+
+    assert_that!(fs::read_to_string(&existing).unwrap()).is_equal_to("existing".to_owned());
+    assert_that!(dir.path().join("new").exists()).is_false();
+    assert_that!(leftovers(dir.path())).is_empty();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn rollback_moves_backups_back() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    let target = dir.path().join("target");
+    let backup = dir.path().join("backup");
+    fs::write(&source, "source").unwrap();
+    fs::create_dir(&target).unwrap();
+    fs::write(target.join("file"), "file").unwrap();
+    fs::set_permissions(target.join("file"), fs::Permissions::from_mode(0o640)).unwrap();
+    std::os::unix::fs::symlink(&source, target.join("link")).unwrap();
+
+    let mut journal = Journal::default();
+    journal.record(&target, &[], None).unwrap();
+    assert_that!(leftovers(dir.path())).is_empty();
+
+    fs::rename(&target, &backup).unwrap();
+    fs::write(&target, "new").unwrap();
+    journal.backed_up(&target, &backup);
+
+    journal.rollback();
+
+    assert_that!(backup.exists()).is_false();
+    assert_that!(fs::read_to_string(target.join("file")).unwrap()).is_equal_to("file".to_owned());
+    assert_that!(fs::metadata(target.join("file")).unwrap().permissions().mode() & 0o7777).is_equal_to(0o640);
+    assert_that!(fs::read_link(target.join("link")).unwrap()).is_equal_to(source);
+  }
+
+  #[test]
+  fn rollback_restores_removed_orphans() {
+    let dir = tempfile::tempdir().unwrap();
+    let orphan = dir.path().join("orphan");
+    fs::write(&orphan, "orphan").unwrap();
+
+    let mut journal = Journal::default();
+    journal.remove(&orphan).unwrap();
+    assert_that!(orphan.exists()).is_false();
+
+    journal.rollback();
+
+    assert_that!(fs::read_to_string(&orphan).unwrap()).is_equal_to("orphan".to_owned());
+    assert_that!(leftovers(dir.path())).is_empty();
+  }
+
+  #[test]
+  fn commit_keeps_new_targets() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    let existing = dir.path().join("existing");
+    let orphan = dir.path().join("orphan");
+    fs::write(&source, "source").unwrap();
+    fs::write(&existing, "existing").unwrap();
+    fs::write(&orphan, "orphan").unwrap();
+
+    let previous = state::Link {
+      source: source.clone(),
+      link_type: LinkType::Copy,
+      created: 0,
+      source_hash: None,
+      target_hash: crate::helpers::hash_path(&existing).ok(),
+      rotz_version: None,
+    };
+
+    let mut journal = Journal::default();
+    journal.remove(&orphan).unwrap();
+    copy(&mut journal, &source, &existing, Some(&previous));
+    journal.commit();
+
+    assert_that!(fs::read_to_string(&existing).unwrap()).is_equal_to("source".to_owned());
+    assert_that!(orphan.exists()).is_false();
+    assert_that!(leftovers(dir.path())).is_empty();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn apply_modes_of_options() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let options = LinkOptions {
+      mode: Some(FileMode(0o600)),
+      dir_mode: Some(FileMode(0o700)),
+      ..LinkOptions::default()
+    };
+    let mut contents = vec![
+      (PathBuf::new(), None, fs::Permissions::from_mode(0o755)),
+      (PathBuf::from("file"), Some(Vec::new()), fs::Permissions::from_mode(0o644)),
+    ];
+
+    apply_modes(&mut contents, &options);
+
+    assert_that!(contents.iter().map(|(_, _, permissions)| permissions.mode()).collect::<Vec<_>>()).is_equal_to(vec![0o700, 0o600]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn copies_get_the_modes_of_their_options() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    let to = dir.path().join("new").join("target");
+    fs::write(&source, "source").unwrap();
+    fs::set_permissions(&source, fs::Permissions::from_mode(0o644)).unwrap();
+
+    let options = LinkOptions {
+      mode: Some(FileMode(0o600)),
+      dir_mode: Some(FileMode(0o700)),
+      ..LinkOptions::default()
+    };
+    let created_dirs = missing_parents(&to);
+    create_link(&source, &to, (&LinkType::Copy, false, &options), &created_dirs, false, None, &|path| {
+      fs::read(path).map_err(|e| Error::Reading(path.to_path_buf(), e))
+    })
+    .unwrap();
+
+    let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+    assert_that!(mode(&to)).is_equal_to(0o600);
+    assert_that!(mode(&dir.path().join("new"))).is_equal_to(0o700);
+    assert_that!(mode(&source)).is_equal_to(0o644);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn orphans_of_unselected_dots_are_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    let selected = dir.path().join("selected");
+    let unselected = dir.path().join("unselected");
+    fs::write(&source, "source").unwrap();
+    std::os::unix::fs::symlink(&source, &selected).unwrap();
+    std::os::unix::fs::symlink(&source, &unselected).unwrap();
+
+    let link = state::Link {
+      source,
+      link_type: LinkType::Symbolic,
+      created: 0,
+      source_hash: None,
+      target_hash: None,
+      rotz_version: None,
+    };
+    let mut state = state::State::default();
+    state.linked.0 = hash_map! {
+      "/a".to_owned(): hash_map! { selected.clone(): link.clone() },
+      "/b".to_owned(): hash_map! { unselected.clone(): link },
+    };
+    let links = vec![("/a".to_owned(), hash_map! {})];
+
+    let orphans = find_orphans(&state, &["/a".to_owned()], &links).unwrap();
+    assert_that!(orphans.iter().map(|(name, to, _)| (name.as_str(), to)).collect::<Vec<_>>()).is_equal_to(vec![("/a", &selected)]);
+
+    remove_orphans(orphans.clone(), &mut state, None, false, true).unwrap();
+    assert_that!(fs::symlink_metadata(&selected)).is_ok();
+    assert_that!(state.linked.0).has_length(2);
+
+    remove_orphans(orphans, &mut state, None, false, false).unwrap();
+    assert_that!(fs::symlink_metadata(&selected)).is_err();
+    assert_that!(fs::symlink_metadata(&unselected)).is_ok();
+    assert_that!(state.linked.0).has_length(1);
+    assert_that!(state.linked.0["/b"]).contains_key(unselected);
+  }
+
+  #[test]
+  fn conflicting_targets() {
+    let links = vec![
+      ("/a".to_owned(), hash_map! { PathBuf::from("file"): hash_map![PathBuf::from("/target"): LinkOptions::default()] }),
+      (
+        "/b".to_owned(),
+        hash_map! { PathBuf::from("file"): hash_map![PathBuf::from("/target"): LinkOptions::default(), PathBuf::from("/other"): LinkOptions::default()] },
+      ),
+    ];
+
+    assert_that!(check_conflicts(Path::new("/dotfiles"), &links)).is_err();
+  }
+
+  #[test]
+  fn distinct_targets() {
+    let links = vec![
+      ("/a".to_owned(), hash_map! { PathBuf::from("file"): hash_map![PathBuf::from("/target"): LinkOptions::default()] }),
+      ("/b".to_owned(), hash_map! { PathBuf::from("file"): hash_map![PathBuf::from("/other"): LinkOptions::default()] }),
+    ];
+
+    assert_that!(check_conflicts(Path::new("/dotfiles"), &links)).is_ok();
+  }
+}
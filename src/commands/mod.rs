@@ -4,9 +4,30 @@ pub use clone::Clone;
 pub mod install;
 pub(crate) use install::Install;
 
+pub mod uninstall;
+pub(crate) use uninstall::Uninstall;
+
+pub mod update;
+pub(crate) use update::Update;
+
 pub mod link;
 pub(crate) use link::Link;
 
+pub mod unlink;
+pub(crate) use unlink::Unlink;
+
+pub mod restore;
+pub(crate) use restore::Restore;
+
+pub mod status;
+pub(crate) use status::Status;
+
+pub mod roots;
+pub(crate) use roots::Roots;
+
+pub mod state;
+pub(crate) use state::State;
+
 pub mod init;
 pub use init::Init;
 
@@ -0,0 +1,138 @@
+use std::{
+  fmt::Debug,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use crossterm::style::{Attribute, Stylize};
+use itertools::Itertools;
+use miette::{Diagnostic, Report, Result};
+use path_absolutize::Absolutize;
+use tap::Pipe;
+#[cfg(feature = "profiling")]
+use tracing::instrument;
+
+use super::{
+  Command,
+  link::{is_modified, links_to, remove_target},
+};
+use crate::{helpers, state};
+
+#[derive(thiserror::Error, Diagnostic, Debug)]
+enum Error {
+  #[error("Could not restore \"{0}\" from \"{1}\"")]
+  #[diagnostic(code(restore::restoring))]
+  Restoring(PathBuf, PathBuf, #[source] std::io::Error),
+
+  #[error("The backup \"{0}\" does not exist")]
+  #[diagnostic(code(restore::missing), help("The backup was removed from the state"))]
+  BackupMissing(PathBuf),
+
+  #[error("Could not remove link at \"{0}\"")]
+  #[diagnostic(code(restore::remove))]
+  Removing(PathBuf, #[source] std::io::Error),
+
+  #[error("The file \"{0}\" already exists and was not created by rotz")]
+  #[diagnostic(code(restore::already_exists), help("Move the file out of the way to restore the backup"))]
+  AlreadyExists(PathBuf),
+
+  #[error("Could not get absolute path of \"{0}\"")]
+  #[diagnostic(code(restore::absolutize))]
+  Absolutize(PathBuf, #[source] std::io::Error),
+}
+
+#[derive(Debug)]
+pub(crate) struct Restore {}
+
+impl Restore {
+  pub const fn new() -> Self {
+    Self {}
+  }
+}
+
+impl Command for Restore {
+  type Args = (crate::cli::Globals, crate::cli::Restore, state::State);
+  type Result = Result<state::State>;
+
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn execute(&self, (globals, restore_command, mut state): Self::Args) -> Self::Result {
+    let targets = restore_command
+      .targets
+      .iter()
+      .map(|t| helpers::resolve_home(t).absolutize().map(|t| t.to_path_buf()).map_err(|e| Error::Absolutize(t.clone(), e)))
+      .collect::<Result<Vec<_>, _>>()?;
+
+    let backups = state
+      .backups
+      .0
+      .keys()
+      .filter(|target| targets.is_empty() || targets.contains(target))
+      .filter_map(|target| state.backups.latest(target).map(|backup| (target.clone(), backup.clone())))
+      .sorted()
+      .collect_vec();
+
+    if backups.is_empty() {
+      println!("Warning: {}", "No backups found".dark_yellow());
+      return state.pipe(Ok);
+    }
+
+    println!("{}Restoring backups{}\n", Attribute::Bold, Attribute::Reset);
+
+    for (target, backup) in backups {
+      state::check_interrupted()?;
+
+      println!("  {} -> {}", backup.to_string_lossy().dark_green(), target.to_string_lossy().dark_green());
+
+      if let Err(err) = restore(&target, &backup, &mut state, globals.dry_run) {
+        eprintln!("\n Error: {:?}", Report::new(err));
+      }
+      state.commit()?;
+    }
+
+    println!();
+
+    state.pipe(Ok)
+  }
+}
+
+/// Replaces the link rotz created at `target` with the backup of the file it replaced
+#[cfg_attr(feature = "profiling", instrument)]
+fn restore(target: &Path, backup: &Path, state: &mut state::State, dry_run: bool) -> std::result::Result<(), Error> {
+  if fs::symlink_metadata(backup).is_err() {
+    if !dry_run {
+      state.backups.pop(target);
+    }
+    return Error::BackupMissing(backup.to_path_buf()).pipe(Err);
+  }
+
+  if fs::symlink_metadata(target).is_ok() {
+    let managed = state
+      .linked
+      .0
+      .values()
+      .filter_map(|l| l.get(target))
+      .any(|link| link.target_hash.as_ref().map_or_else(|| links_to(&link.source, target), |hash| !is_modified(target, hash)));
+
+    if !managed {
+      return Error::AlreadyExists(target.to_path_buf()).pipe(Err);
+    }
+
+    if !dry_run {
+      remove_target(target).map_err(|e| Error::Removing(target.to_path_buf(), e))?;
+    }
+  }
+
+  if dry_run {
+    return ().pipe(Ok);
+  }
+
+  state::backups::restore(backup, target).map_err(|e| Error::Restoring(target.to_path_buf(), backup.to_path_buf(), e))?;
+
+  for links in state.linked.0.values_mut() {
+    links.remove(target);
+  }
+  state.linked.0.retain(|_, links| !links.is_empty());
+  state.backups.pop(target);
+
+  ().pipe(Ok)
+}
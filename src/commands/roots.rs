@@ -0,0 +1,128 @@
+use std::{fmt::Debug, path::PathBuf};
+
+use crossterm::style::{Attribute, Stylize};
+use miette::{Diagnostic, Result};
+use tap::Pipe;
+#[cfg(feature = "profiling")]
+use tracing::instrument;
+
+use super::Command;
+use crate::{cli, state};
+
+#[derive(thiserror::Error, Diagnostic, Debug)]
+enum Error {
+  #[error("rotz has no state for \"{0}\"")]
+  #[diagnostic(code(roots::not_found), help("Run rotz roots list to see the dotfiles repositories rotz keeps state for"))]
+  NotFound(PathBuf),
+
+  #[error("\"{0}\" is the current dotfiles repository")]
+  #[diagnostic(code(roots::same), help("Select the repository to migrate to with --dotfiles"))]
+  SameRoot(PathBuf),
+
+  #[error("Could not get absolute path of \"{0}\"")]
+  #[diagnostic(code(roots::absolutize))]
+  Absolutize(PathBuf, #[source] std::io::Error),
+}
+
+#[derive(Debug)]
+pub(crate) struct Roots {}
+
+impl Roots {
+  pub const fn new() -> Self {
+    Self {}
+  }
+}
+
+impl Command for Roots {
+  type Args = (cli::Globals, cli::Roots, state::State);
+  type Result = Result<state::State>;
+
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn execute(&self, (globals, roots_command, state): Self::Args) -> Self::Result {
+    match roots_command {
+      cli::Roots::List => list(&state)?,
+      cli::Roots::Migrate { from } => return migrate(&from, state, globals.dry_run),
+    }
+
+    state.pipe(Ok)
+  }
+}
+
+#[cfg_attr(feature = "profiling", instrument)]
+fn list(current: &state::State) -> Result<()> {
+  let roots = state::roots()?;
+
+  println!("{}Dotfiles repositories{}\n", Attribute::Bold, Attribute::Reset);
+
+  if !roots.iter().any(|r| r.dotfiles == current.dotfiles) {
+    println!("  {} {}", current.dotfiles.to_string_lossy().dark_blue(), "(current, no state yet)".dark_green());
+  }
+
+  for root in &roots {
+    let mut notes = vec![format!("{} dots linked", root.linked.0.len()), format!("{} dots installed", root.installed.0.len())];
+    if root.dotfiles == current.dotfiles {
+      notes.insert(0, "current".to_owned());
+    }
+
+    let notes = format!("({})", notes.join(", "));
+    if root.dotfiles.exists() {
+      println!("  {} {}", root.dotfiles.to_string_lossy().dark_blue(), notes.dark_green());
+    } else {
+      println!("  {} {} {}", root.dotfiles.to_string_lossy().dark_blue(), notes.dark_green(), "does not exist".dark_yellow());
+    }
+  }
+
+  println!();
+
+  ().pipe(Ok)
+}
+
+/// Moves the state of the repository at `from` into `state`
+///
+/// The links keep their sources so the next link run recognizes them and points them into the current repository
+#[cfg_attr(feature = "profiling", instrument)]
+fn migrate(from: &std::path::Path, mut state: state::State, dry_run: bool) -> Result<state::State> {
+  let from = state::resolve_root(from).map_err(|e| Error::Absolutize(from.to_path_buf(), e))?;
+
+  if from == state.dotfiles {
+    return Err(Error::SameRoot(from).into());
+  }
+
+  let old = state::roots()?.into_iter().find(|r| r.dotfiles == from).ok_or_else(|| Error::NotFound(from.clone()))?;
+
+  println!(
+    "{}Migrating state from {} to {}{}\n",
+    Attribute::Bold,
+    from.to_string_lossy().dark_blue(),
+    state.dotfiles.to_string_lossy().dark_blue(),
+    Attribute::Reset
+  );
+
+  for (name, links) in old.linked.0 {
+    for (to, link) in links {
+      println!("  {} -> {}", link.source.to_string_lossy().dark_green(), to.to_string_lossy().dark_green());
+      if !dry_run {
+        state.linked.0.entry(name.clone()).or_default().entry(to).or_insert(link);
+      }
+    }
+  }
+
+  println!("\nRun rotz link to point the links into the current repository\n");
+
+  if dry_run {
+    return state.pipe(Ok);
+  }
+
+  for (name, install) in old.installed.0 {
+    state.installed.0.entry(name).or_insert(install);
+  }
+
+  for (target, backups) in old.backups.0 {
+    state.backups.0.entry(target).or_default().extend(backups);
+  }
+
+  state.commit()?;
+  state::remove_root(&from)?;
+
+  state.pipe(Ok)
+}
@@ -0,0 +1,442 @@
+use std::{
+  collections::BTreeSet,
+  fmt::Debug,
+  fs,
+  path::{Path, PathBuf},
+  time::UNIX_EPOCH,
+};
+
+use crossterm::style::{Attribute, Stylize};
+use itertools::Itertools;
+use miette::{Diagnostic, Result};
+use tap::Pipe;
+#[cfg(feature = "profiling")]
+use tracing::instrument;
+use wax::Pattern;
+
+use super::{
+  Command,
+  link::{links_to, unfold_links},
+};
+use crate::{FILE_EXTENSIONS, FileFormat, cli, config::LinkType, helpers, state, templating};
+
+#[derive(thiserror::Error, Diagnostic, Debug)]
+enum Error {
+  #[error("Could not write the exported state to \"{0}\"")]
+  #[diagnostic(code(state::export::write))]
+  Exporting(PathBuf, #[source] std::io::Error),
+
+  #[error("Could not read the state file \"{0}\"")]
+  #[diagnostic(code(state::import::read))]
+  Importing(PathBuf, #[source] std::io::Error),
+}
+
+pub(crate) struct State<'a> {
+  config: crate::config::Config,
+  engine: templating::Engine<'a>,
+}
+
+impl Debug for State<'_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("State").field("config", &self.config).finish()
+  }
+}
+
+impl<'a> State<'a> {
+  pub const fn new(config: crate::config::Config, engine: templating::Engine<'a>) -> Self {
+    Self { config, engine }
+  }
+
+  /// Adds the targets of the dots which already point to their dotfiles to the state
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn adopt(&self, state: &mut state::State, dots: &[String], dry_run: bool) -> Result<()> {
+    let links = crate::dot::read_dots(&self.config.dotfiles, dots, &self.config, &self.engine)?
+      .into_iter()
+      .filter_map(|d| d.1.links.map(|l| (d.0, l)))
+      .collect_vec()
+      .pipe(|links| unfold_links(&self.config.dotfiles, links))?;
+
+    for (name, link) in links.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+      println!("{}Adopting {}{}\n", Attribute::Bold, name.as_str().dark_blue(), Attribute::Reset);
+
+      let base_path = self.config.dotfiles.join(&name[1..]);
+      for (from, tos) in link {
+        let from = base_path.join(&from);
+        for (to, options) in tos {
+          let to = helpers::resolve_home(&to);
+          let link_type = options.link_type.as_ref().unwrap_or(&self.config.link_type);
+
+          if state.linked.0.get(&name).is_some_and(|l| l.contains_key(&to)) {
+            println!("  {} {}", to.to_string_lossy().dark_green(), "is already in the state".dark_grey());
+            continue;
+          }
+
+          let Some(link) = adoptable(&from, &to, link_type) else {
+            println!("  {} {}", to.to_string_lossy().dark_green(), "does not point to its dotfile, skipping".dark_yellow());
+            continue;
+          };
+
+          println!(
+            "  {} -> {} {}",
+            from.to_string_lossy().dark_green(),
+            to.to_string_lossy().dark_green(),
+            format!("({})", link.link_type.to_string().to_lowercase()).dark_grey()
+          );
+          if !dry_run {
+            state.linked.0.entry(name.clone()).or_default().insert(to, link);
+          }
+        }
+      }
+
+      println!();
+    }
+
+    ().pipe(Ok)
+  }
+}
+
+impl Command for State<'_> {
+  type Args = (cli::Globals, cli::State, state::State);
+  type Result = Result<state::State>;
+
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn execute(&self, (globals, state_command, mut state): Self::Args) -> Self::Result {
+    match state_command {
+      cli::State::Show { dots } => show(&state, &dots.add_root().dots)?,
+      cli::State::Export { format, output } => export(&state, format, output.as_deref())?,
+      cli::State::Import { file } => return import(&file, state, globals.dry_run),
+      cli::State::Forget { dots } => forget(&mut state, &cli::Dots { dots }.add_root().dots, globals.dry_run)?,
+      cli::State::Prune => prune(&mut state, globals.dry_run),
+      cli::State::Adopt { dots } => self.adopt(&mut state, &dots.add_root().dots, globals.dry_run)?,
+    }
+
+    state.pipe(Ok)
+  }
+}
+
+#[cfg_attr(feature = "profiling", instrument)]
+fn show(state: &state::State, dots: &[String]) -> Result<()> {
+  let globs = helpers::glob_from_vec(dots, None)?;
+  let names = state.linked.0.keys().chain(state.installed.0.keys()).filter(|n| globs.is_match(n.as_str())).collect::<BTreeSet<_>>();
+
+  println!("{}State of {}{}\n", Attribute::Bold, state.dotfiles.to_string_lossy().dark_blue(), Attribute::Reset);
+
+  for name in names {
+    println!("{}{}{}", Attribute::Bold, name.as_str().dark_blue(), Attribute::Reset);
+
+    if let Some(links) = state.linked.0.get(name) {
+      for (to, link) in links.iter().sorted_by_key(|l| l.0) {
+        println!(
+          "  {} -> {} {}",
+          link.source.to_string_lossy().dark_green(),
+          to.to_string_lossy().dark_green(),
+          format!("({}, created {})", link.link_type.to_string().to_lowercase(), age(link.created)).dark_grey()
+        );
+      }
+    }
+
+    if let Some(install) = state.installed.0.get(name) {
+      let result = if install.success { "succeeded".dark_green() } else { "failed".dark_yellow() };
+      println!("  install {result} {}", age(install.time).dark_grey());
+    }
+
+    println!();
+  }
+
+  for (index, (target, backup)) in state
+    .backups
+    .0
+    .iter()
+    .sorted_by_key(|b| b.0)
+    .flat_map(|(target, backups)| backups.iter().map(move |b| (target, b)))
+    .enumerate()
+  {
+    if index == 0 {
+      println!("{}Backups{}", Attribute::Bold, Attribute::Reset);
+    }
+    println!("  {} -> {}", target.to_string_lossy().dark_green(), backup.to_string_lossy().dark_green());
+  }
+
+  ().pipe(Ok)
+}
+
+#[cfg_attr(feature = "profiling", instrument)]
+fn export(state: &state::State, format: Option<FileFormat>, output: Option<&Path>) -> Result<()> {
+  let format = match (format, output) {
+    (Some(format), _) => format,
+    (None, Some(output)) => FileFormat::try_from(output)?,
+    (None, None) => FILE_EXTENSIONS[0].1,
+  };
+
+  let exported = state::serialize_state(state, format).map_err(state::Error::Serializing)?;
+
+  if let Some(output) = output {
+    fs::write(output, exported).map_err(|e| Error::Exporting(output.to_path_buf(), e))?;
+  } else {
+    print!("{exported}");
+  }
+
+  ().pipe(Ok)
+}
+
+/// Replaces `state` with the state in `file` which is assigned to the current dotfiles repository
+#[cfg_attr(feature = "profiling", instrument)]
+fn import(file: &Path, state: state::State, dry_run: bool) -> Result<state::State> {
+  let format = FileFormat::try_from(file)?;
+  let content = fs::read_to_string(file).map_err(|e| Error::Importing(file.to_path_buf(), e))?;
+  let (mut imported, _) = state::parse(&content, format, file)?;
+
+  println!(
+    "Imported {} linked dots, {} installed dots and {} backups",
+    imported.linked.0.len().to_string().dark_green(),
+    imported.installed.0.len().to_string().dark_green(),
+    imported.backups.0.len().to_string().dark_green()
+  );
+
+  if dry_run {
+    return state.pipe(Ok);
+  }
+
+  imported.dotfiles = state.dotfiles;
+  imported.pipe(Ok)
+}
+
+#[cfg_attr(feature = "profiling", instrument)]
+fn forget(state: &mut state::State, dots: &[String], dry_run: bool) -> Result<()> {
+  let globs = helpers::glob_from_vec(dots, None)?;
+  let names = state
+    .linked
+    .0
+    .keys()
+    .chain(state.installed.0.keys())
+    .filter(|n| globs.is_match(n.as_str()))
+    .cloned()
+    .collect::<BTreeSet<_>>();
+
+  if names.is_empty() {
+    println!("Warning: {}", "No matching dots found in the state".dark_yellow());
+  }
+
+  for name in names {
+    println!("  x {}", name.as_str().dark_blue());
+    if !dry_run {
+      state.linked.0.remove(&name);
+      state.installed.0.remove(&name);
+    }
+  }
+
+  ().pipe(Ok)
+}
+
+#[cfg_attr(feature = "profiling", instrument)]
+fn prune(state: &mut state::State, dry_run: bool) {
+  let exists = |path: &Path| fs::symlink_metadata(path).is_ok();
+
+  for (name, links) in &mut state.linked.0 {
+    for to in links.keys().filter(|to| !exists(to)).sorted() {
+      println!("  x {} {}", name.as_str().dark_blue(), to.to_string_lossy().dark_green());
+    }
+    if !dry_run {
+      links.retain(|to, _| exists(to));
+    }
+  }
+
+  for (target, backups) in state.backups.0.iter().sorted_by_key(|b| b.0) {
+    for backup in backups.iter().filter(|backup| !exists(backup)) {
+      println!("  x backup {} of {}", backup.to_string_lossy().dark_green(), target.to_string_lossy().dark_green());
+    }
+  }
+
+  if !dry_run {
+    state.linked.0.retain(|_, links| !links.is_empty());
+    for backups in state.backups.0.values_mut() {
+      backups.retain(|backup| exists(backup));
+    }
+    state.backups.0.retain(|_, backups| !backups.is_empty());
+  }
+}
+
+/// Builds the state entry for `to` if it is a link to `from` or a copy of it
+#[cfg_attr(feature = "profiling", instrument)]
+fn adoptable(from: &Path, to: &Path, link_type: &LinkType) -> Option<state::Link> {
+  let metadata = fs::symlink_metadata(to).ok()?;
+
+  let (link_type, target_hash) = if links_to(from, to) && from.exists() {
+    (if metadata.is_symlink() { LinkType::Symbolic } else { LinkType::Hard }, None)
+  } else if link_type.is_copy() && !metadata.is_symlink() {
+    let hash = helpers::hash_path(to).ok()?;
+    if helpers::hash_path(from).ok()? != hash {
+      return None;
+    }
+    (LinkType::Copy, Some(hash))
+  } else {
+    return None;
+  };
+
+  state::Link {
+    source: from.to_path_buf(),
+    link_type,
+    created: metadata.modified().ok().and_then(|m| m.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_secs()),
+    source_hash: helpers::hash_path(from).ok(),
+    target_hash,
+    rotz_version: None,
+  }
+  .pipe(Some)
+}
+
+/// Describes how long ago the unix timestamp `time` was
+fn age(time: u64) -> String {
+  let seconds = state::timestamp().saturating_sub(time);
+
+  match seconds {
+    0..60 => "just now".to_owned(),
+    60..3600 => format!("{} minutes ago", seconds / 60),
+    3600..86400 => format!("{} hours ago", seconds / 3600),
+    _ => format!("{} days ago", seconds / 86400),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    fs,
+    path::{Path, PathBuf},
+  };
+
+  use rstest::rstest;
+  use speculoos::prelude::*;
+  use velcro::hash_map;
+
+  use super::{adoptable, forget, import, prune};
+  use crate::{FileFormat, config::LinkType, helpers, state};
+
+  /// What is at the target when it is adopted
+  #[cfg(unix)]
+  #[derive(Debug)]
+  enum Target {
+    Missing,
+    Symlink,
+    HardLink,
+    Written(&'static str),
+  }
+
+  fn link(source: &Path) -> state::Link {
+    state::Link {
+      source: source.to_path_buf(),
+      link_type: LinkType::Symbolic,
+      created: 0,
+      source_hash: None,
+      target_hash: None,
+      rotz_version: None,
+    }
+  }
+
+  fn install() -> state::Install {
+    state::Install {
+      hash: String::new(),
+      time: 0,
+      success: true,
+      rotz_version: String::new(),
+    }
+  }
+
+  #[test]
+  fn import_assigns_current_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("state.yaml");
+    let mut exported = state::State::default();
+    exported.dotfiles = PathBuf::from("/other");
+    exported.linked.0 = hash_map! { "/git".to_owned(): hash_map! { PathBuf::from("/home/.gitconfig"): link(Path::new("/other/git/.gitconfig")) } };
+    fs::write(&file, state::serialize_state(&exported, FileFormat::Yaml).unwrap()).unwrap();
+
+    let mut current = state::State::default();
+    current.dotfiles = PathBuf::from("/dotfiles");
+    let imported = import(&file, current, false).unwrap();
+
+    assert_that!(imported.dotfiles).is_equal_to(PathBuf::from("/dotfiles"));
+    assert_that!(imported.linked.0).contains_key("/git".to_owned());
+
+    let current = import(&file, state::State::default(), true).unwrap();
+    assert_that!(current.linked.0).is_empty();
+  }
+
+  #[test]
+  fn forget_removes_matching_dots() {
+    let mut state = state::State::default();
+    state.linked.0 = hash_map! {
+      "/git".to_owned(): hash_map! { PathBuf::from("/home/.gitconfig"): link(Path::new("/dotfiles/git/.gitconfig")) },
+      "/nvim".to_owned(): hash_map! { PathBuf::from("/home/.config/nvim"): link(Path::new("/dotfiles/nvim/config")) },
+    };
+    state.installed.0 = hash_map! { "/git".to_owned(): install(), "/nvim".to_owned(): install() };
+
+    forget(&mut state, &["/git".to_owned()], true).unwrap();
+    assert_that!(state.linked.0).has_length(2);
+    assert_that!(state.installed.0).has_length(2);
+
+    forget(&mut state, &["/git".to_owned()], false).unwrap();
+    assert_that!(state.linked.0).has_length(1);
+    assert_that!(state.linked.0).contains_key("/nvim".to_owned());
+    assert_that!(state.installed.0).has_length(1);
+    assert_that!(state.installed.0).contains_key("/nvim".to_owned());
+  }
+
+  #[test]
+  fn prune_removes_missing_targets_and_backups() {
+    let dir = tempfile::tempdir().unwrap();
+    let existing = dir.path().join("existing");
+    let missing = dir.path().join("missing");
+    let backup = dir.path().join("backup");
+    fs::write(&existing, "existing").unwrap();
+    fs::write(&backup, "backup").unwrap();
+
+    let mut state = state::State::default();
+    state.linked.0 = hash_map! {
+      "/a".to_owned(): hash_map! { existing.clone(): link(&existing), missing.clone(): link(&missing) },
+      "/b".to_owned(): hash_map! { missing.clone(): link(&missing) },
+    };
+    state.backups.0 = hash_map! {
+      existing.clone(): vec![backup.clone(), dir.path().join("missing-backup")],
+      missing: vec![dir.path().join("missing-backup")],
+    };
+
+    prune(&mut state, true);
+    assert_that!(state.linked.0).has_length(2);
+    assert_that!(state.backups.0).has_length(2);
+
+    prune(&mut state, false);
+    assert_that!(state.linked.0).has_length(1);
+    assert_that!(state.linked.0["/a"].keys().collect::<Vec<_>>()).is_equal_to(vec![&existing]);
+    assert_that!(state.backups.0).has_length(1);
+    assert_that!(state.backups.0[&existing]).is_equal_to(vec![backup]);
+  }
+
+  #[cfg(unix)]
+  #[rstest]
+  #[case::missing(Target::Missing, LinkType::Symbolic, None)]
+  #[case::symlink(Target::Symlink, LinkType::Symbolic, Some(LinkType::Symbolic))]
+  #[case::hard_link(Target::HardLink, LinkType::Hard, Some(LinkType::Hard))]
+  #[case::copy(Target::Written("content"), LinkType::Copy, Some(LinkType::Copy))]
+  #[case::copy_differs(Target::Written("edited"), LinkType::Copy, None)]
+  #[case::not_a_copy(Target::Written("content"), LinkType::Symbolic, None)]
+  fn adopt(#[case] target: Target, #[case] configured: LinkType, #[case] expected: Option<LinkType>) {
+    let dir = tempfile::tempdir().unwrap();
+    let from = dir.path().join("source");
+    let to = dir.path().join("target");
+    fs::write(&from, "content").unwrap();
+
+    match target {
+      Target::Missing => {}
+      Target::Symlink => std::os::unix::fs::symlink(&from, &to).unwrap(),
+      Target::HardLink => fs::hard_link(&from, &to).unwrap(),
+      Target::Written(content) => fs::write(&to, content).unwrap(),
+    }
+
+    let link = adoptable(&from, &to, &configured);
+
+    assert_that!(link.as_ref().map(|l| l.link_type.clone())).is_equal_to(expected.clone());
+    if let Some(link) = link {
+      assert_that!(link.source).is_equal_to(from.clone());
+      assert_that!(link.target_hash).is_equal_to(expected.filter(LinkType::is_copy).map(|_| helpers::hash_path(&from).unwrap()));
+    }
+  }
+}
@@ -0,0 +1,312 @@
+use std::{
+  collections::HashSet,
+  fmt::Debug,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use crossterm::style::{Attribute, Stylize};
+use itertools::Itertools;
+use miette::{Diagnostic, Result};
+use path_absolutize::Absolutize;
+use serde::Serialize;
+use strum::Display;
+use tap::Pipe;
+#[cfg(feature = "profiling")]
+use tracing::instrument;
+use wax::Pattern;
+
+use super::{
+  Command,
+  link::{Contents, expected_contents, is_modified, is_written, links_to, unfold_links},
+};
+use crate::{
+  cli::StatusFormat,
+  config::{Config, LinkType},
+  helpers, state,
+  templating::{self, Parameters},
+};
+
+#[derive(thiserror::Error, Diagnostic, Debug)]
+enum Error {
+  #[error("{0} of the links are out of sync")]
+  #[diagnostic(code(status::drift), help("Run rotz link to update the links"))]
+  Drift(usize),
+
+  #[cfg(feature = "json")]
+  #[error("Could not serialize the status")]
+  #[diagnostic(code(status::serialize))]
+  Serializing(#[source] serde_json::Error),
+}
+
+/// Status of a link target compared to the dotfiles and the state
+#[derive(Serialize, Display, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+enum LinkStatus {
+  /// The target is linked to its dotfile
+  Linked,
+  /// The target was removed
+  Missing,
+  /// The dotfile the target links to or was written from does not exist anymore
+  Broken,
+  /// The target links to a different file
+  Elsewhere,
+  /// The target was replaced by a file which is not a link to the dotfile
+  Replaced,
+  /// The content of a copied or rendered target was changed
+  Modified,
+  /// The dotfile or the link type changed since the target was created
+  Outdated,
+  /// The target is defined in the dotfiles but was not linked yet
+  NotLinked,
+  /// The target was linked but is not defined in the dotfiles anymore
+  Orphaned,
+}
+
+impl LinkStatus {
+  const fn in_sync(self) -> bool {
+    matches!(self, Self::Linked)
+  }
+}
+
+#[derive(Serialize, Debug)]
+struct Entry {
+  status: LinkStatus,
+  dot: String,
+  target: PathBuf,
+  source: PathBuf,
+}
+
+pub(crate) struct Status<'a> {
+  config: Config,
+  engine: templating::Engine<'a>,
+}
+
+impl Debug for Status<'_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Status").field("config", &self.config).finish()
+  }
+}
+
+impl<'a> Status<'a> {
+  pub const fn new(config: crate::config::Config, engine: templating::Engine<'a>) -> Self {
+    Self { config, engine }
+  }
+}
+
+impl Command for Status<'_> {
+  type Args = (crate::cli::Status, state::State);
+  type Result = Result<()>;
+
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn execute(&self, (status_command, state): Self::Args) -> Self::Result {
+    let links = crate::dot::read_dots(&self.config.dotfiles, &status_command.dots, &self.config, &self.engine)?
+      .into_iter()
+      .filter_map(|d| d.1.links.map(|l| (d.0, l)))
+      .collect_vec()
+      .pipe(|links| unfold_links(&self.config.dotfiles, links))?;
+
+    let mut entries = Vec::new();
+    let mut defined = HashSet::new();
+
+    for (name, link) in links {
+      let base_path = self.config.dotfiles.join(&name[1..]);
+      let parameters = Parameters { config: &self.config, name: &name };
+      for (from, tos) in link {
+        let from = base_path.join(&from);
+        for (to, options) in tos {
+          let to = helpers::resolve_home(&to);
+          let link_type = options.link_type.as_ref().unwrap_or(&self.config.link_type);
+
+          let status = state.linked.0.get(&name).and_then(|l| l.get(&to)).map_or(LinkStatus::NotLinked, |link| {
+            let contents = (link_type.is_copy() || link_type.is_template())
+              .then(|| expected_contents(&self.engine, &from, link_type, &options, &parameters))
+              .flatten();
+            check(&to, &from, link_type, link, contents.as_ref())
+          });
+
+          defined.insert((name.clone(), to.clone()));
+          entries.push(Entry {
+            status,
+            dot: name.clone(),
+            target: to,
+            source: from.clone(),
+          });
+        }
+      }
+    }
+
+    let dots = helpers::glob_from_vec(&status_command.dots, None)?;
+    for (name, links) in state.linked.0.iter().filter(|l| dots.is_match(l.0.as_str())) {
+      for (to, link) in links {
+        if !defined.contains(&(name.clone(), to.clone())) {
+          entries.push(Entry {
+            status: LinkStatus::Orphaned,
+            dot: name.clone(),
+            target: to.clone(),
+            source: link.source.clone(),
+          });
+        }
+      }
+    }
+
+    entries.sort_by(|a, b| (&a.dot, &a.target).cmp(&(&b.dot, &b.target)));
+
+    match status_command.format {
+      StatusFormat::Human => print_human(&entries),
+      StatusFormat::Porcelain => {
+        for entry in &entries {
+          println!("{}\t{}\t{}\t{}", entry.status, entry.dot, entry.target.to_string_lossy(), entry.source.to_string_lossy());
+        }
+      }
+      #[cfg(feature = "json")]
+      StatusFormat::Json => println!("{}", serde_json::to_string_pretty(&entries).map_err(Error::Serializing)?),
+    }
+
+    let drift = entries.iter().filter(|e| !e.status.in_sync()).count();
+    if drift > 0 {
+      return Err(Error::Drift(drift).into());
+    }
+
+    ().pipe(Ok)
+  }
+}
+
+/// Compares a target recorded in the state as `link` with the dotfile it should be linked to as `link_type`
+///
+/// `contents` is what a copied or rendered target would be written with now
+#[cfg_attr(feature = "profiling", instrument(skip(contents)))]
+fn check(to: &Path, from: &Path, link_type: &LinkType, link: &state::Link, contents: Option<&Contents>) -> LinkStatus {
+  let Ok(metadata) = fs::symlink_metadata(to) else {
+    return LinkStatus::Missing;
+  };
+
+  if let Some(hash) = &link.target_hash {
+    return if is_modified(to, hash) {
+      LinkStatus::Modified
+    } else if !from.exists() {
+      LinkStatus::Broken
+    } else if link.link_type != *link_type || contents.map_or_else(|| link.source_hash != helpers::hash_path(from).ok(), |contents| !is_written(to, contents)) {
+      LinkStatus::Outdated
+    } else {
+      LinkStatus::Linked
+    };
+  }
+
+  let status = check_link(to, from, &link.link_type, &metadata);
+  if status == LinkStatus::Linked && link.link_type != *link_type {
+    LinkStatus::Outdated
+  } else {
+    status
+  }
+}
+
+/// Compares a symbolic or hard link with the dotfile it should point to
+fn check_link(to: &Path, from: &Path, link_type: &LinkType, metadata: &fs::Metadata) -> LinkStatus {
+  if metadata.is_symlink() {
+    let points_to = fs::read_link(to)
+      .ok()
+      .map(|source| to.parent().map_or_else(|| source.clone(), |parent| parent.join(&source)))
+      .and_then(|source| source.absolutize().ok().map(|s| s.to_path_buf()));
+
+    return if !links_to(from, to) && points_to.is_none_or(|points_to| points_to != from) {
+      LinkStatus::Elsewhere
+    } else if from.exists() {
+      LinkStatus::Linked
+    } else {
+      LinkStatus::Broken
+    };
+  }
+
+  if link_type.is_hard() && links_to(from, to) { LinkStatus::Linked } else { LinkStatus::Replaced }
+}
+
+fn print_human(entries: &[Entry]) {
+  for (dot, entries) in &entries.iter().chunk_by(|e| &e.dot) {
+    println!("{}Status of {}{}\n", Attribute::Bold, dot.as_str().dark_blue(), Attribute::Reset);
+
+    for entry in entries {
+      let status = entry.status.to_string().replace('_', " ");
+      println!(
+        "  {} -> {} {}",
+        entry.source.to_string_lossy().dark_green(),
+        entry.target.to_string_lossy().dark_green(),
+        if entry.status.in_sync() { status.dark_green() } else { status.dark_yellow() }
+      );
+    }
+
+    println!();
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use std::{fs, path::PathBuf};
+
+  use rstest::rstest;
+  use speculoos::prelude::*;
+
+  use super::{LinkStatus, check};
+  use crate::{config::LinkType, helpers, state};
+
+  /// What is at the target when the status is checked
+  #[derive(Debug)]
+  enum Target {
+    Missing,
+    LinkToSource,
+    LinkElsewhere,
+    Written(&'static str),
+  }
+
+  #[rstest]
+  #[case::missing(Target::Missing, Some("content"), LinkType::Symbolic, LinkType::Symbolic, LinkStatus::Missing)]
+  #[case::linked(Target::LinkToSource, Some("content"), LinkType::Symbolic, LinkType::Symbolic, LinkStatus::Linked)]
+  #[case::broken(Target::LinkToSource, None, LinkType::Symbolic, LinkType::Symbolic, LinkStatus::Broken)]
+  #[case::elsewhere(Target::LinkElsewhere, Some("content"), LinkType::Symbolic, LinkType::Symbolic, LinkStatus::Elsewhere)]
+  #[case::replaced(Target::Written("content"), Some("content"), LinkType::Symbolic, LinkType::Symbolic, LinkStatus::Replaced)]
+  #[case::link_type_changed(Target::LinkToSource, Some("content"), LinkType::Symbolic, LinkType::Copy, LinkStatus::Outdated)]
+  #[case::copied(Target::Written("content"), Some("content"), LinkType::Copy, LinkType::Copy, LinkStatus::Linked)]
+  #[case::modified(Target::Written("edited"), Some("content"), LinkType::Copy, LinkType::Copy, LinkStatus::Modified)]
+  #[case::source_changed(Target::Written("content"), Some("changed"), LinkType::Copy, LinkType::Copy, LinkStatus::Outdated)]
+  #[case::source_removed(Target::Written("content"), None, LinkType::Copy, LinkType::Copy, LinkStatus::Broken)]
+  #[case::copy_type_changed(Target::Written("content"), Some("content"), LinkType::Copy, LinkType::Symbolic, LinkStatus::Outdated)]
+  fn status(#[case] target: Target, #[case] source: Option<&str>, #[case] recorded: LinkType, #[case] configured: LinkType, #[case] expected: LinkStatus) {
+    let dir = tempfile::tempdir().unwrap();
+    let from = dir.path().join("source");
+    let to = dir.path().join("target");
+
+    fs::write(&from, "content").unwrap();
+    let mut target_hash = None;
+    match target {
+      Target::Missing => {}
+      Target::LinkToSource => std::os::unix::fs::symlink(&from, &to).unwrap(),
+      Target::LinkElsewhere => std::os::unix::fs::symlink(dir.path().join("other"), &to).unwrap(),
+      Target::Written(content) => {
+        fs::write(&to, "content").unwrap();
+        target_hash = recorded.is_copy().then(|| helpers::hash_path(&to).unwrap());
+        fs::write(&to, content).unwrap();
+      }
+    }
+
+    let link = state::Link {
+      source: from.clone(),
+      link_type: recorded,
+      created: 0,
+      source_hash: helpers::hash_path(&from).ok(),
+      target_hash,
+      rotz_version: None,
+    };
+
+    match source {
+      Some(source) => fs::write(&from, source).unwrap(),
+      None => fs::remove_file(&from).unwrap(),
+    }
+
+    let contents = source
+      .filter(|_| configured.is_copy())
+      .map(|source| vec![(PathBuf::new(), Some(source.as_bytes().to_vec()), fs::metadata(&from).unwrap().permissions())]);
+
+    assert_that!(check(&to, &from, &configured, &link, contents.as_ref())).is_equal_to(expected);
+  }
+}
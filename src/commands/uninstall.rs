@@ -0,0 +1,56 @@
+use std::fmt::Debug;
+
+use miette::Result;
+#[cfg(feature = "profiling")]
+use tracing::instrument;
+
+use super::{
+  Command, Install, Unlink,
+  install::{Action, Options, uninstall_graph},
+};
+use crate::{cli, state, templating};
+
+pub(crate) struct Uninstall<'a> {
+  install: Install<'a>,
+}
+
+impl Debug for Uninstall<'_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Uninstall").field("install", &self.install).finish()
+  }
+}
+
+impl<'a> Uninstall<'a> {
+  pub const fn new(config: crate::config::Config, engine: templating::Engine<'a>) -> Self {
+    Self {
+      install: Install::new(config, engine),
+    }
+  }
+}
+
+impl Command for Uninstall<'_> {
+  type Args = (cli::Globals, cli::Uninstall, state::State);
+  type Result = Result<state::State>;
+
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn execute(&self, (globals, uninstall_command, mut state): Self::Args) -> Self::Result {
+    let graph = uninstall_graph(&self.install.dots()?, &uninstall_command.dots)?;
+
+    let options = Options {
+      continue_on_error: uninstall_command.continue_on_error,
+      reinstall: false,
+      jobs: 1,
+    };
+    self.install.run(graph, Action::Uninstall, &mut state, (&globals, &options))?;
+
+    Unlink::new().execute((
+      globals,
+      cli::Unlink {
+        dots: uninstall_command.dots,
+        restore: uninstall_command.restore,
+        force: false,
+      },
+      state,
+    ))
+  }
+}
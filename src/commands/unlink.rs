@@ -0,0 +1,255 @@
+use std::{
+  fmt::Debug,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use crossterm::style::{Attribute, Stylize};
+use itertools::Itertools;
+use miette::{Diagnostic, Report, Result};
+use tap::Pipe;
+#[cfg(feature = "profiling")]
+use tracing::instrument;
+use wax::Pattern;
+
+use super::{
+  Command,
+  link::{is_modified, links_to, remove_target},
+};
+use crate::{helpers, state};
+
+#[derive(thiserror::Error, Diagnostic, Debug)]
+enum Error {
+  #[error("Could not remove link from \"{0}\" to \"{1}\"")]
+  #[diagnostic(code(unlink::remove))]
+  Removing(PathBuf, PathBuf, #[source] std::io::Error),
+
+  #[error("Could not back up \"{0}\"")]
+  #[diagnostic(code(unlink::backup))]
+  BackingUp(PathBuf, #[source] std::io::Error),
+
+  #[error("Could not restore \"{0}\" from \"{1}\"")]
+  #[diagnostic(code(unlink::restore))]
+  Restoring(PathBuf, PathBuf, #[source] std::io::Error),
+}
+
+#[derive(Debug)]
+pub(crate) struct Unlink {}
+
+impl Unlink {
+  pub const fn new() -> Self {
+    Self {}
+  }
+}
+
+impl Command for Unlink {
+  type Args = (crate::cli::Globals, crate::cli::Unlink, state::State);
+  type Result = Result<state::State>;
+
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn execute(&self, (globals, unlink_command, mut state): Self::Args) -> Self::Result {
+    let dots = helpers::glob_from_vec(&unlink_command.dots, None)?;
+
+    let selected = state
+      .linked
+      .0
+      .iter()
+      .filter(|l| dots.is_match(l.0.as_str()))
+      .map(|(name, links)| (name.clone(), links.clone()))
+      .collect_vec();
+    let mut removed = Vec::new();
+
+    for (name, links) in selected {
+      println!("{}Unlinking {}{}\n", Attribute::Bold, name.as_str().dark_blue(), Attribute::Reset);
+
+      for (to, link) in links {
+        state::check_interrupted()?;
+
+        let hash = link.target_hash.as_ref();
+        let managed = hash.map_or_else(|| links_to(&link.source, &to), |hash| !is_modified(&to, hash));
+
+        let forget = if fs::symlink_metadata(&to).is_err() {
+          println!("  - {} {}", to.to_string_lossy().dark_green(), "does not exist".dark_yellow());
+          removed.push(to.clone());
+          true
+        } else if !managed && !unlink_command.force {
+          let reason = if hash.is_some() {
+            "was modified since rotz created it, skipping"
+          } else {
+            "was not created by rotz, skipping"
+          };
+          println!("  ! {} {}", to.to_string_lossy().dark_green(), reason.dark_yellow());
+          false
+        } else if !managed {
+          back_up(&to, &mut state, globals.dry_run)
+        } else if globals.dry_run {
+          println!("  x {}", to.to_string_lossy().dark_green());
+          removed.push(to.clone());
+          true
+        } else if let Err(err) = if hash.is_some() { remove_target(&to) } else { fs::remove_file(&to) } {
+          eprintln!("\n Error: {:?}", Report::new(Error::Removing(link.source.clone(), to.clone(), err)));
+          false
+        } else {
+          println!("  x {}", to.to_string_lossy().dark_green());
+          removed.push(to.clone());
+          true
+        };
+
+        if forget && !globals.dry_run {
+          state.linked.remove(&name, &to);
+          state.commit()?;
+        }
+      }
+
+      println!();
+    }
+
+    if unlink_command.restore {
+      restore_backups(&removed, &mut state, globals.dry_run)?;
+    }
+
+    state.pipe(Ok)
+  }
+}
+
+/// Moves a target which was modified or not created by rotz into the backup store, returns whether it was moved
+#[cfg_attr(feature = "profiling", instrument)]
+fn back_up(to: &Path, state: &mut state::State, dry_run: bool) -> bool {
+  if dry_run {
+    println!("  x {} {}", to.to_string_lossy().dark_green(), "would be backed up".dark_yellow());
+    return true;
+  }
+
+  match state::backups::create(to) {
+    Ok(backup) => {
+      println!("  x {} {} {}", to.to_string_lossy().dark_green(), "backed up to".dark_yellow(), backup.to_string_lossy().dark_green());
+      state.backups.push(to.to_path_buf(), backup);
+      true
+    }
+    Err(err) => {
+      eprintln!("\n Error: {:?}", Report::new(Error::BackingUp(to.to_path_buf(), err)));
+      false
+    }
+  }
+}
+
+#[cfg_attr(feature = "profiling", instrument)]
+fn restore_backups(removed: &[PathBuf], state: &mut state::State, dry_run: bool) -> Result<(), state::Error> {
+  let backups = removed.iter().filter_map(|to| state.backups.latest(to).map(|backup| (to.clone(), backup.clone()))).collect::<Vec<_>>();
+
+  if backups.is_empty() {
+    return ().pipe(Ok);
+  }
+
+  println!("{}Restoring backups{}\n", Attribute::Bold, Attribute::Reset);
+
+  for (to, backup) in backups {
+    state::check_interrupted()?;
+
+    println!("  {} -> {}", backup.to_string_lossy().dark_green(), to.to_string_lossy().dark_green());
+
+    if dry_run {
+      continue;
+    }
+
+    if let Err(err) = state::backups::restore(&backup, &to) {
+      eprintln!("\n Error: {:?}", Report::new(Error::Restoring(to.clone(), backup.clone(), err)));
+    } else {
+      state.backups.pop(&to);
+      state.commit()?;
+    }
+  }
+
+  println!();
+
+  ().pipe(Ok)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use std::{fs, path::Path};
+
+  use speculoos::prelude::*;
+
+  use super::Unlink;
+  use crate::{
+    cli::{Globals, Unlink as UnlinkArgs},
+    commands::Command,
+    config::LinkType,
+    helpers, state,
+  };
+
+  fn unlink(state: state::State, restore: bool) -> state::State {
+    let args = UnlinkArgs {
+      dots: vec!["/**".to_owned()],
+      restore,
+      force: false,
+    };
+    Unlink::new().execute((Globals { dry_run: false }, args, state)).unwrap()
+  }
+
+  fn linked(from: &Path, to: &Path, link_type: LinkType, target_hash: Option<String>) -> state::State {
+    let mut state = state::State::default();
+    let link = state::Link {
+      source: from.to_path_buf(),
+      link_type,
+      created: 0,
+      source_hash: None,
+      target_hash,
+      rotz_version: None,
+    };
+    state.linked.0.entry("/dot".to_owned()).or_default().insert(to.to_path_buf(), link);
+    state
+  }
+
+  #[test]
+  fn skips_modified_targets() {
+    let dir = tempfile::tempdir().unwrap();
+    let from = dir.path().join("source");
+    let to = dir.path().join("target");
+    fs::write(&from, "content").unwrap();
+    fs::write(&to, "content").unwrap();
+    let state = linked(&from, &to, LinkType::Copy, Some(helpers::hash_path(&to).unwrap()));
+    fs::write(&to, "edited").unwrap();
+
+    let state = unlink(state, false);
+
+    assert_that!(fs::read_to_string(&to).unwrap()).is_equal_to("edited".to_owned());
+    assert_that!(state.linked.0["/dot"]).contains_key(to);
+  }
+
+  #[test]
+  fn skips_targets_not_created_by_rotz() {
+    let dir = tempfile::tempdir().unwrap();
+    let from = dir.path().join("source");
+    let to = dir.path().join("target");
+    fs::write(&from, "content").unwrap();
+    fs::write(&to, "foreign").unwrap();
+
+    let state = unlink(linked(&from, &to, LinkType::Symbolic, None), false);
+
+    assert_that!(fs::read_to_string(&to).unwrap()).is_equal_to("foreign".to_owned());
+    assert_that!(state.linked.0["/dot"]).contains_key(to);
+  }
+
+  #[test]
+  fn restores_backups_of_removed_targets() {
+    let dir = tempfile::tempdir().unwrap();
+    let from = dir.path().join("source");
+    let to = dir.path().join("target");
+    let backup = dir.path().join("backup");
+    fs::write(&from, "content").unwrap();
+    fs::write(&backup, "original").unwrap();
+    std::os::unix::fs::symlink(&from, &to).unwrap();
+    let mut state = linked(&from, &to, LinkType::Symbolic, None);
+    state.backups.push(to.clone(), backup.clone());
+
+    let state = unlink(state, true);
+
+    assert_that!(fs::symlink_metadata(&to).unwrap().file_type().is_symlink()).is_false();
+    assert_that!(fs::read_to_string(&to).unwrap()).is_equal_to("original".to_owned());
+    assert_that!(backup.exists()).is_false();
+    assert_that!(state.linked.0.get("/dot").is_none_or(std::collections::HashMap::is_empty)).is_true();
+    assert_that!(state.backups.0).is_empty();
+  }
+}
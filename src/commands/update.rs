@@ -0,0 +1,49 @@
+use std::fmt::Debug;
+
+use miette::Result;
+use tap::Pipe;
+#[cfg(feature = "profiling")]
+use tracing::instrument;
+
+use super::{
+  Command, Install,
+  install::{Action, Options, update_graph},
+};
+use crate::{cli, state, templating};
+
+pub(crate) struct Update<'a> {
+  install: Install<'a>,
+}
+
+impl Debug for Update<'_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Update").field("install", &self.install).finish()
+  }
+}
+
+impl<'a> Update<'a> {
+  pub const fn new(config: crate::config::Config, engine: templating::Engine<'a>) -> Self {
+    Self {
+      install: Install::new(config, engine),
+    }
+  }
+}
+
+impl Command for Update<'_> {
+  type Args = (cli::Globals, cli::Update, state::State);
+  type Result = Result<state::State>;
+
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn execute(&self, (globals, update_command, mut state): Self::Args) -> Self::Result {
+    let graph = update_graph(&self.install.dots()?, &update_command.dots)?;
+
+    let options = Options {
+      continue_on_error: update_command.continue_on_error,
+      reinstall: false,
+      jobs: update_command.jobs,
+    };
+    self.install.run(graph, Action::Update, &mut state, (&globals, &options))?;
+
+    state.pipe(Ok)
+  }
+}
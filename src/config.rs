@@ -20,13 +20,20 @@ use tracing::instrument;
 
 use crate::{FileFormat, USER_DIRS, helpers};
 
-#[derive(Debug, ValueEnum, Clone, Display, Deserialize, Serialize, EnumIs)]
-#[cfg_attr(test, derive(Dummy, PartialEq, Eq))]
+#[derive(Debug, ValueEnum, Clone, Display, Deserialize, Serialize, EnumIs, PartialEq, Eq)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(rename_all = "lowercase")]
 pub enum LinkType {
   /// Uses symbolic links for linking
+  #[serde(alias = "Symbolic")]
   Symbolic,
   /// Uses hard links for linking
+  #[serde(alias = "Hard")]
   Hard,
+  /// Copies the dotfiles instead of linking them
+  Copy,
+  /// Renders the dotfiles as templates and writes the output
+  Template,
 }
 
 #[cfg(test)]
@@ -55,6 +62,10 @@ pub struct Config {
   /// Which link type to use for linking dotfiles
   pub(crate) link_type: LinkType,
 
+  /// Whether symbolic links point to their source relative to the directory of the link
+  #[serde(default)]
+  pub(crate) relative_links: bool,
+
   /// The command used to spawn processess.
   /// Use handlebars templates `{{ cmd }}` as placeholder for the cmd set in the dot.
   /// E.g. `"bash -c {{ quote "" cmd }}"`.
@@ -70,6 +81,7 @@ impl Default for Config {
     Self {
       dotfiles: USER_DIRS.home_dir().join(".dotfiles"),
       link_type: LinkType::Symbolic,
+      relative_links: false,
       #[cfg(windows)]
       shell_command: Some("powershell -NoProfile -C {{ quote \"\" cmd }}".to_owned()),
       #[cfg(all(not(target_os = "macos"), unix))]
@@ -29,26 +29,31 @@ mod defaults;
 mod error;
 mod repr;
 pub use error::Error;
+pub use repr::{FileMode, LinkOptions};
 
 #[derive(Clone, Debug)]
 pub struct Installs {
   pub(crate) cmd: String,
   pub(crate) depends: HashSet<String>,
+  pub(crate) check: Option<String>,
+  pub(crate) binary: Option<String>,
 }
 
 impl From<repr::InstallsCanonical> for Option<Installs> {
   fn from(from: repr::InstallsCanonical) -> Self {
     match from {
       repr::InstallsCanonical::None(_) => None,
-      repr::InstallsCanonical::Full { cmd, depends } => Installs { cmd, depends }.pipe(Some),
+      repr::InstallsCanonical::Full { cmd, depends, check, binary } => Installs { cmd, depends, check, binary }.pipe(Some),
     }
   }
 }
 
 #[derive(Default, Clone, Debug)]
 pub struct Dot {
-  pub(crate) links: Option<HashMap<PathBuf, HashSet<PathBuf>>>,
+  pub(crate) links: Option<HashMap<PathBuf, HashMap<PathBuf, LinkOptions>>>,
   pub(crate) installs: Option<Installs>,
+  pub(crate) uninstalls: Option<Installs>,
+  pub(crate) updates: Option<Installs>,
   pub(crate) depends: Option<HashSet<String>>,
 }
 
@@ -61,6 +66,8 @@ fn from_str_with_defaults(s: &str, format: FileFormat, defaults: Option<&Capabil
     Dot {
       links: capabilities.links,
       installs: capabilities.installs.and_then(Into::into),
+      uninstalls: capabilities.uninstalls.and_then(Into::into),
+      updates: capabilities.updates.and_then(Into::into),
       depends: capabilities.depends,
     }
   } else {
@@ -164,7 +171,7 @@ fn canonicalize_dots(dots: Vec<(String, Dot)>) -> Result<Vec<(String, Dot)>, hel
   let dots = dots.into_iter().map(|mut dot| {
     let name = helpers::absolutize_virtually(Path::new(&dot.0)).map_err(|e| Error::ParseName(dot.0.clone(), e))?;
 
-    if let Some(installs) = &mut dot.1.installs {
+    for installs in [&mut dot.1.installs, &mut dot.1.uninstalls, &mut dot.1.updates].into_iter().flatten() {
       let depends = installs.depends.iter().map(|dependency| {
         let dependency_base = Path::new(&name).parent().unwrap_or_else(|| Path::new("")).join(dependency);
 
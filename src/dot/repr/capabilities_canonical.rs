@@ -10,21 +10,23 @@ use serde::Deserialize;
 use tap::Pipe;
 #[cfg(feature = "profiling")]
 use tracing::instrument;
-use velcro::hash_set;
+use velcro::hash_map;
 
 use crate::{
   helpers::{self, MultipleErrors},
   templating::{Engine, Parameters},
 };
 
-use super::{CapabilitiesComplex, DotCanonical, InstallsCanonical, LinksComplex, Merge};
+use super::{CapabilitiesComplex, DotCanonical, InstallsCanonical, LinkOptions, LinksComplex, Merge};
 
 #[derive(Deserialize, Clone, Default, Debug)]
 #[cfg_attr(test, derive(Dummy))]
 #[serde(deny_unknown_fields)]
 pub struct CapabilitiesCanonical {
-  pub links: Option<HashMap<PathBuf, HashSet<PathBuf>>>,
+  pub links: Option<HashMap<PathBuf, HashMap<PathBuf, LinkOptions>>>,
   pub installs: Option<InstallsCanonical>,
+  pub uninstalls: Option<InstallsCanonical>,
+  pub updates: Option<InstallsCanonical>,
   pub depends: Option<HashSet<String>>,
 }
 
@@ -39,14 +41,19 @@ impl From<CapabilitiesComplex> for CapabilitiesCanonical {
             (
               l.0,
               match l.1 {
-                LinksComplex::One(o) => hash_set!(o),
-                LinksComplex::Many(m) => m,
+                LinksComplex::One(o) => {
+                  let (target, options) = o.into();
+                  hash_map!(target: options)
+                }
+                LinksComplex::Many(m) => m.into_iter().map(Into::into).collect(),
               },
             )
           })
           .collect::<HashMap<_, _>>()
       }),
       installs: value.installs.map(Into::into),
+      uninstalls: value.uninstalls.map(Into::into),
+      updates: value.updates.map(Into::into),
       depends: value.depends,
     }
   }
@@ -98,13 +105,28 @@ impl Merge<Option<CapabilitiesCanonical>> for Option<CapabilitiesCanonical> {
 
 impl Merge<Self> for CapabilitiesCanonical {
   #[cfg_attr(feature = "profiling", instrument)]
-  fn merge(mut self, Self { mut links, installs, depends }: Self) -> Self {
+  fn merge(
+    mut self,
+    Self {
+      mut links,
+      installs,
+      uninstalls,
+      updates,
+      depends,
+    }: Self,
+  ) -> Self {
     if let Some(self_links) = &mut self.links {
       if let Some(merge_links) = &mut links {
         for l in &mut *merge_links {
           if self_links.contains_key(l.0) {
             let self_links_value = self_links.get_mut(l.0).unwrap();
-            self_links_value.extend(l.1.clone());
+            for (target, options) in l.1.clone() {
+              let options = match self_links_value.remove(&target) {
+                Some(self_options) => self_options.merge(options),
+                None => options,
+              };
+              self_links_value.insert(target, options);
+            }
           } else {
             self_links.insert(l.0.clone(), l.1.clone());
           }
@@ -114,40 +136,9 @@ impl Merge<Self> for CapabilitiesCanonical {
       self.links = links;
     }
 
-    if let Some(i) = &mut self.installs {
-      if let Some(installs) = installs {
-        if installs.is_none() {
-          self.installs = None;
-        } else {
-          let cmd_outer: String;
-          let mut depends_outer;
-
-          match installs {
-            InstallsCanonical::Full { cmd, depends } => {
-              cmd_outer = cmd;
-              depends_outer = depends;
-            }
-            InstallsCanonical::None(_) => unreachable!(),
-          }
-
-          *i = match i {
-            InstallsCanonical::None(_) => InstallsCanonical::Full {
-              cmd: cmd_outer,
-              depends: depends_outer,
-            },
-            InstallsCanonical::Full { depends, .. } => {
-              depends_outer.extend(depends.clone());
-              InstallsCanonical::Full {
-                cmd: cmd_outer,
-                depends: depends_outer,
-              }
-            }
-          };
-        }
-      }
-    } else {
-      self.installs = installs;
-    }
+    self.installs = merge_installs(self.installs, installs);
+    self.uninstalls = merge_installs(self.uninstalls, uninstalls);
+    self.updates = merge_installs(self.updates, updates);
 
     if let Some(d) = &mut self.depends {
       if let Some(depends) = depends {
@@ -160,3 +151,49 @@ impl Merge<Self> for CapabilitiesCanonical {
     self
   }
 }
+
+/// Merges `merge` into `installs`. The command of `merge` replaces the one of `installs` and the dependencies of both are combined
+#[cfg_attr(feature = "profiling", instrument)]
+fn merge_installs(installs: Option<InstallsCanonical>, merge: Option<InstallsCanonical>) -> Option<InstallsCanonical> {
+  let Some(mut installs) = installs else {
+    return merge;
+  };
+
+  if let Some(merge) = merge {
+    if merge.is_none() {
+      return None;
+    }
+
+    let InstallsCanonical::Full {
+      cmd: cmd_outer,
+      depends: mut depends_outer,
+      check: mut check_outer,
+      binary: mut binary_outer,
+    } = merge
+    else {
+      unreachable!()
+    };
+
+    installs = match installs {
+      InstallsCanonical::None(_) => InstallsCanonical::Full {
+        cmd: cmd_outer,
+        depends: depends_outer,
+        check: check_outer,
+        binary: binary_outer,
+      },
+      InstallsCanonical::Full { depends, check, binary, .. } => {
+        depends_outer.extend(depends);
+        check_outer = check_outer.or(check);
+        binary_outer = binary_outer.or(binary);
+        InstallsCanonical::Full {
+          cmd: cmd_outer,
+          depends: depends_outer,
+          check: check_outer,
+          binary: binary_outer,
+        }
+      }
+    };
+  }
+
+  installs.pipe(Some)
+}
@@ -15,6 +15,8 @@ use super::{DotSimplified, InstallsComplex, LinksComplex};
 pub(super) struct CapabilitiesComplex {
   pub(super) links: Option<HashMap<PathBuf, LinksComplex>>,
   pub(super) installs: Option<InstallsComplex>,
+  pub(super) uninstalls: Option<InstallsComplex>,
+  pub(super) updates: Option<InstallsComplex>,
   pub(super) depends: Option<HashSet<String>>,
 }
 
@@ -23,6 +25,8 @@ impl From<DotSimplified> for CapabilitiesComplex {
     Self {
       depends: from.depends,
       installs: from.installs,
+      uninstalls: from.uninstalls,
+      updates: from.updates,
       links: from.links,
     }
   }
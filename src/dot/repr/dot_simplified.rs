@@ -15,5 +15,7 @@ use super::{InstallsComplex, LinksComplex};
 pub(super) struct DotSimplified {
   pub(super) links: Option<HashMap<PathBuf, LinksComplex>>,
   pub(super) installs: Option<InstallsComplex>,
+  pub(super) uninstalls: Option<InstallsComplex>,
+  pub(super) updates: Option<InstallsComplex>,
   pub(super) depends: Option<HashSet<String>>,
 }
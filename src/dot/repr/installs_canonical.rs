@@ -18,6 +18,10 @@ pub enum InstallsCanonical {
     cmd: String,
     #[serde(default)]
     depends: HashSet<String>,
+    #[serde(default)]
+    check: Option<String>,
+    #[serde(default)]
+    binary: Option<String>,
   },
 }
 
@@ -25,8 +29,13 @@ impl From<InstallsComplex> for InstallsCanonical {
   fn from(value: InstallsComplex) -> Self {
     match value {
       InstallsComplex::None(t) => InstallsCanonical::None(t),
-      InstallsComplex::Simple(cmd) => InstallsCanonical::Full { cmd, depends: hash_set!() },
-      InstallsComplex::Full { cmd, depends } => InstallsCanonical::Full { cmd, depends },
+      InstallsComplex::Simple(cmd) => InstallsCanonical::Full {
+        cmd,
+        depends: hash_set!(),
+        check: None,
+        binary: None,
+      },
+      InstallsComplex::Full { cmd, depends, check, binary } => InstallsCanonical::Full { cmd, depends, check, binary },
     }
   }
 }
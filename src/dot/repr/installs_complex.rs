@@ -16,5 +16,9 @@ pub(super) enum InstallsComplex {
     cmd: String,
     #[serde(default)]
     depends: HashSet<String>,
+    #[serde(default)]
+    check: Option<String>,
+    #[serde(default)]
+    binary: Option<String>,
   },
 }
@@ -0,0 +1,68 @@
+#[cfg(test)]
+use fake::Dummy;
+use serde::Deserialize;
+#[cfg(feature = "profiling")]
+use tracing::instrument;
+
+use super::Merge;
+use crate::config::LinkType;
+
+/// Options of a single link which override the global config
+#[derive(Deserialize, Clone, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(deny_unknown_fields)]
+pub struct LinkOptions {
+  #[serde(rename = "type")]
+  pub link_type: Option<LinkType>,
+  /// Creates a symbolic link pointing to its source relative to the directory of the link
+  pub relative: Option<bool>,
+  /// Links every file in the source directory individually instead of linking the directory itself
+  #[serde(default)]
+  pub unfold: bool,
+  /// Globs of files relative to the source directory which are not linked when unfolding
+  #[serde(default)]
+  pub ignore: Vec<String>,
+  /// File mode of copied or rendered targets
+  pub mode: Option<FileMode>,
+  /// File mode of the directories rotz creates for the target
+  pub dir_mode: Option<FileMode>,
+  /// Owner of copied or rendered targets and of created directories as user name or id
+  pub owner: Option<String>,
+  /// Group of copied or rendered targets and of created directories as group name or id
+  pub group: Option<String>,
+}
+
+impl Merge<Self> for LinkOptions {
+  /// Options set in `merge` replace the ones of `self`, options it leaves unset are kept
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn merge(self, merge: Self) -> Self {
+    Self {
+      link_type: merge.link_type.or(self.link_type),
+      relative: merge.relative.or(self.relative),
+      unfold: merge.unfold || self.unfold,
+      ignore: if merge.ignore.is_empty() { self.ignore } else { merge.ignore },
+      mode: merge.mode.or(self.mode),
+      dir_mode: merge.dir_mode.or(self.dir_mode),
+      owner: merge.owner.or(self.owner),
+      group: merge.group.or(self.group),
+    }
+  }
+}
+
+/// Unix permission bits written as an octal string e.g. `"600"`
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(try_from = "String")]
+pub struct FileMode(pub u32);
+
+impl TryFrom<String> for FileMode {
+  type Error = String;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    u32::from_str_radix(value.trim_start_matches("0o"), 8)
+      .ok()
+      .filter(|mode| *mode <= 0o7777)
+      .map(Self)
+      .ok_or_else(|| format!("\"{value}\" is not an octal file mode"))
+  }
+}
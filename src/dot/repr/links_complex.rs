@@ -1,14 +1,71 @@
-use std::{collections::HashSet, path::PathBuf};
+use std::path::PathBuf;
 
 #[cfg(test)]
 use fake::Dummy;
 use serde::Deserialize;
 
+use crate::config::LinkType;
+
+use super::{FileMode, LinkOptions};
+
 #[derive(Deserialize, Clone, Debug)]
 #[serde(untagged)]
 #[cfg_attr(test, derive(Dummy))]
 #[serde(deny_unknown_fields)]
 pub(super) enum LinksComplex {
-  One(PathBuf),
-  Many(HashSet<PathBuf>),
+  One(LinkComplex),
+  Many(Vec<LinkComplex>),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+#[cfg_attr(test, derive(Dummy))]
+#[serde(deny_unknown_fields)]
+pub(super) enum LinkComplex {
+  Simple(PathBuf),
+  Full {
+    target: PathBuf,
+    #[serde(rename = "type")]
+    link_type: Option<LinkType>,
+    relative: Option<bool>,
+    #[serde(default)]
+    unfold: bool,
+    #[serde(default)]
+    ignore: Vec<String>,
+    mode: Option<FileMode>,
+    dir_mode: Option<FileMode>,
+    owner: Option<String>,
+    group: Option<String>,
+  },
+}
+
+impl From<LinkComplex> for (PathBuf, LinkOptions) {
+  fn from(value: LinkComplex) -> Self {
+    match value {
+      LinkComplex::Simple(target) => (target, LinkOptions::default()),
+      LinkComplex::Full {
+        target,
+        link_type,
+        relative,
+        unfold,
+        ignore,
+        mode,
+        dir_mode,
+        owner,
+        group,
+      } => (
+        target,
+        LinkOptions {
+          link_type,
+          relative,
+          unfold,
+          ignore,
+          mode,
+          dir_mode,
+          owner,
+          group,
+        },
+      ),
+    }
+  }
 }
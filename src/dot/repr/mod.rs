@@ -21,6 +21,8 @@ mod capabilities_canonical;
 pub use capabilities_canonical::*;
 mod links_complex;
 use links_complex::LinksComplex;
+mod link_options;
+pub use link_options::*;
 mod installs_complex;
 use installs_complex::InstallsComplex;
 mod installs_canonical;
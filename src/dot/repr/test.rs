@@ -15,3 +15,19 @@ fn parse_dot_complex() {
     .is_some()
     .matches(|i| matches!(i, InstallsComplex::Simple(s) if s == "test"));
 }
+
+#[test]
+fn parse_installs_check() {
+  let dot_string = r"
+  global:
+    installs:
+      cmd: test
+      check: test --version
+      binary: test
+  ";
+
+  let dot = DotComplex::parse(dot_string, crate::FileFormat::Yaml).unwrap();
+  assert_that!(dot.selectors.get("global").unwrap().installs)
+    .is_some()
+    .matches(|i| matches!(i, InstallsComplex::Full { check: Some(c), binary: Some(b), .. } if c == "test --version" && b == "test"));
+}
@@ -10,6 +10,7 @@ use figment::{util::map, value};
 static CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
   dotfiles: "dotfiles".into(),
   link_type: LinkType::Hard,
+  relative_links: false,
   shell_command: "shell_command".to_owned().into(),
   variables: map! {
     "test".to_owned() => "test".into(),
@@ -10,6 +10,7 @@ use figment::{util::map, value};
 static CONFIG: LazyLock<Config> = LazyLock::new(|| Config {
   dotfiles: "dotfiles".into(),
   link_type: LinkType::Hard,
+  relative_links: false,
   shell_command: "shell_command".to_owned().into(),
   variables: map! {
     "test".to_owned() => "test".into(),
@@ -41,3 +42,6 @@ mod s04;
 mod s05;
 mod s06;
 mod s07;
+mod s08;
+mod s09;
+mod s10;
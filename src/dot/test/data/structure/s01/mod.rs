@@ -2,11 +2,12 @@ use std::path::PathBuf;
 
 use speculoos::{assert_that, prelude::*};
 use tap::Tap;
-use velcro::hash_set;
+use velcro::hash_map;
 
 use crate::helpers::Select;
 
 use super::{get_handlebars, get_parameters};
+use crate::dot::LinkOptions;
 
 #[test]
 fn structure() {
@@ -14,8 +15,8 @@ fn structure() {
 
   assert_that!(dot.links)
     .is_some()
-    .tap_mut(|l| l.contains_entry(PathBuf::from("k01"), &hash_set![PathBuf::from("v01")]))
-    .tap_mut(|l| l.contains_entry(PathBuf::from("k02"), &hash_set![PathBuf::from("v02")]));
+    .tap_mut(|l| l.contains_entry(PathBuf::from("k01"), &hash_map![PathBuf::from("v01"): LinkOptions::default()]))
+    .tap_mut(|l| l.contains_entry(PathBuf::from("k02"), &hash_map![PathBuf::from("v02"): LinkOptions::default()]));
 
   assert_that!(dot.installs).is_some().select(|i| &i.cmd).is_equal_to("i01".to_owned());
 
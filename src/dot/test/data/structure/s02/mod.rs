@@ -2,11 +2,12 @@ use std::path::PathBuf;
 
 use speculoos::{assert_that, prelude::*};
 use tap::Tap;
-use velcro::hash_set;
+use velcro::hash_map;
 
 use crate::helpers::Select;
 
 use super::{get_handlebars, get_parameters};
+use crate::dot::LinkOptions;
 
 #[test]
 fn structure() {
@@ -14,8 +15,18 @@ fn structure() {
 
   assert_that!(dot.links)
     .is_some()
-    .tap_mut(|l| l.contains_entry(PathBuf::from("k01"), &hash_set![PathBuf::from("v01a"), PathBuf::from("v01b")]))
-    .tap_mut(|l| l.contains_entry(PathBuf::from("k02"), &hash_set![PathBuf::from("v02a"), PathBuf::from("v02b")]));
+    .tap_mut(|l| {
+      l.contains_entry(
+        PathBuf::from("k01"),
+        &hash_map![PathBuf::from("v01a"): LinkOptions::default(), PathBuf::from("v01b"): LinkOptions::default()],
+      );
+    })
+    .tap_mut(|l| {
+      l.contains_entry(
+        PathBuf::from("k02"),
+        &hash_map![PathBuf::from("v02a"): LinkOptions::default(), PathBuf::from("v02b"): LinkOptions::default()],
+      );
+    });
 
   assert_that!(dot.installs)
     .is_some()
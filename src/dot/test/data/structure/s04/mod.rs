@@ -2,9 +2,10 @@ use std::path::PathBuf;
 
 use speculoos::{assert_that, prelude::*};
 use tap::Tap;
-use velcro::hash_set;
+use velcro::hash_map;
 
 use super::{get_handlebars, get_parameters};
+use crate::dot::LinkOptions;
 
 #[test]
 fn structure() {
@@ -12,6 +13,11 @@ fn structure() {
 
   assert_that!(dot.links)
     .is_some()
-    .tap_mut(|l| l.contains_entry(PathBuf::from("k01"), &hash_set![PathBuf::from("v01")]))
-    .tap_mut(|l| l.contains_entry(PathBuf::from("k02"), &hash_set![PathBuf::from("v02a"), PathBuf::from("v02b")]));
+    .tap_mut(|l| l.contains_entry(PathBuf::from("k01"), &hash_map![PathBuf::from("v01"): LinkOptions::default()]))
+    .tap_mut(|l| {
+      l.contains_entry(
+        PathBuf::from("k02"),
+        &hash_map![PathBuf::from("v02a"): LinkOptions::default(), PathBuf::from("v02b"): LinkOptions::default()],
+      );
+    });
 }
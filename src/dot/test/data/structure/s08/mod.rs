@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use speculoos::{assert_that, prelude::*};
+use tap::Tap;
+use velcro::hash_map;
+
+use super::{get_handlebars, get_parameters};
+use crate::{config::LinkType, dot::LinkOptions};
+
+#[test]
+fn structure() {
+  let dot = crate::parse!("yaml", &get_handlebars(), &get_parameters());
+
+  assert_that!(dot.links)
+    .is_some()
+    .tap_mut(|l| {
+      l.contains_entry(
+        PathBuf::from("k01"),
+        &hash_map![
+          PathBuf::from("v01a"): LinkOptions { link_type: Some(LinkType::Hard), ..LinkOptions::default() },
+          PathBuf::from("v01b"): LinkOptions { link_type: Some(LinkType::Copy), ..LinkOptions::default() }
+        ],
+      );
+    })
+    .tap_mut(|l| {
+      l.contains_entry(
+        PathBuf::from("k02"),
+        &hash_map![PathBuf::from("v02"): LinkOptions { link_type: Some(LinkType::Template), ..LinkOptions::default() }],
+      );
+    });
+}
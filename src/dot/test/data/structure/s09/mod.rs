@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+use speculoos::{assert_that, prelude::*};
+use tap::Tap;
+use velcro::hash_map;
+
+use super::{get_handlebars, get_parameters};
+use crate::dot::LinkOptions;
+
+#[test]
+fn structure() {
+  let dot = crate::parse!("yaml", &get_handlebars(), &get_parameters());
+
+  assert_that!(dot.links)
+    .is_some()
+    .tap_mut(|l| {
+      l.contains_entry(
+        PathBuf::from("k01"),
+        &hash_map![PathBuf::from("v01"): LinkOptions { unfold: true, ignore: vec!["*.md".to_owned(), ".git".to_owned()], ..LinkOptions::default() }],
+      );
+    })
+    .tap_mut(|l| l.contains_entry(PathBuf::from("k02"), &hash_map![PathBuf::from("v02"): LinkOptions::default()]));
+}
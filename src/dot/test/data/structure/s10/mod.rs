@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+
+use speculoos::{assert_that, prelude::*};
+use velcro::hash_map;
+
+use super::{get_handlebars, get_parameters};
+use crate::{
+  config::LinkType,
+  dot::{FileMode, LinkOptions},
+};
+
+#[test]
+fn structure() {
+  let dot = crate::parse!("yaml", &get_handlebars(), &get_parameters());
+
+  assert_that!(dot.links).is_some().contains_entry(
+    PathBuf::from("k01"),
+    &hash_map![PathBuf::from("v01"): LinkOptions {
+      link_type: Some(LinkType::Copy),
+      mode: Some(FileMode(0o600)),
+      dir_mode: Some(FileMode(0o700)),
+      owner: Some("root".to_owned()),
+      group: Some("0".to_owned()),
+      ..LinkOptions::default()
+    }],
+  );
+}
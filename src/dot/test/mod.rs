@@ -3,8 +3,17 @@ use std::path::Path;
 use speculoos::prelude::*;
 use tap::Tap;
 
-use super::{defaults::Defaults, read_dots};
-use crate::{helpers::Select, templating::test::get_handlebars};
+use super::{
+  defaults::Defaults,
+  from_str_with_defaults, read_dots,
+  repr::{CapabilitiesCanonical, DotCanonical, FileMode},
+};
+use crate::{
+  FileFormat,
+  config::{Config, LinkType},
+  helpers::Select,
+  templating::{Parameters, test::get_handlebars},
+};
 
 mod data;
 
@@ -133,3 +142,28 @@ fn read_all_file_formats() {
   assert_that!(dots).mapped_contains(|d| &d.0, &"/test02");
   assert_that!(dots).mapped_contains(|d| &d.0, &"/test03");
 }
+
+#[test]
+fn merge_link_options_with_defaults() {
+  let engine = get_handlebars();
+  let config = Config::default();
+  let parameters = Parameters { config: &config, name: "app" };
+  let defaults = r#"
+  links:
+    config:
+      target: ~/.config/app
+      type: copy
+      mode: "600"
+  "#;
+  let dot = r"
+  links:
+    config: ~/.config/app
+  ";
+
+  let defaults = CapabilitiesCanonical::from(DotCanonical::parse(defaults, FileFormat::Yaml).unwrap(), &engine, &parameters).unwrap();
+  let dot = from_str_with_defaults(dot, FileFormat::Yaml, Some(&defaults), &engine, &parameters).unwrap();
+
+  let options = &dot.links.unwrap()[Path::new("config")][Path::new("~/.config/app")];
+  assert_that!(options.link_type).is_equal_to(Some(LinkType::Copy));
+  assert_that!(options.mode).is_equal_to(Some(FileMode(0o600)));
+}
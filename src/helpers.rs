@@ -1,8 +1,11 @@
 use std::{
+  env,
   ffi::OsStr,
   fmt::Debug,
+  fs,
   io::{self, Write},
-  path::{Path, PathBuf},
+  iter,
+  path::{Component, Path, PathBuf},
   process,
 };
 
@@ -10,11 +13,13 @@ use itertools::Itertools;
 use miette::{Diagnostic, Result};
 use path_absolutize::Absolutize;
 use path_slash::PathExt;
+use sha2::{Digest, Sha256};
 #[cfg(test)]
 use speculoos::assert_that;
 use tap::Pipe;
 #[cfg(feature = "profiling")]
 use tracing::instrument;
+use walkdir::WalkDir;
 use wax::{Any, Glob};
 
 use crate::{FILE_EXTENSIONS, FileFormat};
@@ -116,6 +121,27 @@ pub fn run_command(cmd: &str, args: &[impl AsRef<OsStr> + Debug], silent: bool,
   String::from_utf8_lossy(&output.stdout).to_string().pipe(Ok)
 }
 
+/// Runs `cmd` like [`run_command`] but returns its output instead of printing it
+#[cfg_attr(feature = "profiling", instrument)]
+pub fn run_command_buffered(cmd: &str, args: &[impl AsRef<OsStr> + Debug], dry_run: bool) -> (Vec<u8>, Result<(), RunError>) {
+  if dry_run {
+    return (Vec::new(), ().pipe(Ok));
+  }
+
+  let output = match process::Command::new(cmd).args(args).stdin(process::Stdio::null()).output() {
+    Ok(output) => output,
+    Err(err) => return (Vec::new(), RunError::Spawn(err).pipe(Err)),
+  };
+
+  let result = if output.status.success() {
+    ().pipe(Ok)
+  } else {
+    RunError::Execute(output.status.code()).pipe(Err)
+  };
+
+  ([output.stdout, output.stderr].concat(), result)
+}
+
 #[derive(thiserror::Error, Diagnostic, Debug)]
 pub enum GlobError {
   #[error("Could not build GlobSet")]
@@ -211,6 +237,63 @@ pub enum ParseError {
   ),
 }
 
+/// Hashes the content of a file or of all files in a directory
+#[cfg_attr(feature = "profiling", instrument)]
+pub fn hash_path(path: &Path) -> Result<String, io::Error> {
+  let mut hasher = Sha256::new();
+
+  for entry in WalkDir::new(path).sort_by_file_name() {
+    let entry = entry?;
+    hasher.update(entry.path().strip_prefix(path).map_err(io::Error::other)?.to_slash_lossy().as_bytes());
+    if entry.path_is_symlink() {
+      hasher.update(fs::read_link(entry.path())?.to_slash_lossy().as_bytes());
+    } else if !entry.file_type().is_dir() {
+      hasher.update(fs::read(entry.path())?);
+    }
+  }
+
+  format!("{:x}", hasher.finalize()).pipe(Ok)
+}
+
+/// Hashes `content`
+pub fn hash(content: impl AsRef<[u8]>) -> String {
+  format!("{:x}", Sha256::digest(content))
+}
+
+/// Looks up the executable `binary` in the directories of the `PATH` environment variable
+#[cfg_attr(feature = "profiling", instrument)]
+pub fn find_binary(binary: &str) -> Option<PathBuf> {
+  let extensions = if cfg!(windows) {
+    env::var("PATHEXT").unwrap_or_default().split(';').filter(|e| !e.is_empty()).map(ToOwned::to_owned).collect_vec()
+  } else {
+    Vec::new()
+  };
+
+  env::split_paths(&env::var_os("PATH")?).find_map(|dir| {
+    iter::once(dir.join(binary))
+      .chain(extensions.iter().map(|extension| dir.join(format!("{binary}{extension}"))))
+      .find(|candidate| candidate.is_file())
+  })
+}
+
+/// Path of `path` relative to the directory `base`, `None` if they do not share a root
+pub fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+  let path = path.components().collect_vec();
+  let base = base.components().collect_vec();
+
+  if path.first() != base.first() {
+    return None;
+  }
+
+  let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+  base[common..]
+    .iter()
+    .map(|_| Component::ParentDir)
+    .chain(path[common..].iter().copied())
+    .collect::<PathBuf>()
+    .pipe(Some)
+}
+
 pub fn resolve_home(path: impl AsRef<Path>) -> PathBuf {
   let path = path.as_ref();
 
@@ -228,7 +311,9 @@ mod tests {
   use miette::Diagnostic;
   use speculoos::prelude::*;
 
-  use crate::helpers::join_err_result;
+  use std::path::{Path, PathBuf};
+
+  use crate::helpers::{join_err_result, relative_path};
 
   #[derive(thiserror::Error, Debug, Diagnostic)]
   #[error("")]
@@ -246,4 +331,18 @@ mod tests {
 
     assert_that!(&joined).is_err().map(|e| &e.0).has_length(2);
   }
+
+  #[test]
+  fn relative_path_sibling() {
+    assert_that!(relative_path(Path::new("/home/user/.dotfiles/git/config"), Path::new("/home/user/.config/git")))
+      .is_some()
+      .is_equal_to(PathBuf::from("../../.dotfiles/git/config"));
+  }
+
+  #[test]
+  fn relative_path_descendant() {
+    assert_that!(relative_path(Path::new("/home/user/.dotfiles/file"), Path::new("/home/user")))
+      .is_some()
+      .is_equal_to(PathBuf::from(".dotfiles/file"));
+  }
 }
@@ -94,6 +94,7 @@ pub(crate) const FILE_EXTENSIONS: &[(&str, FileFormat)] = &[
 ];
 
 #[derive(Debug, Display, Clone, Copy)]
+#[cfg_attr(test, derive(fake::Dummy, PartialEq, Eq))]
 pub(crate) enum FileFormat {
   #[cfg(feature = "yaml")]
   #[strum(to_string = "yaml")]
@@ -168,16 +169,40 @@ fn run() -> Result<(), miette::Report> {
   let config = read_config(&cli)?;
 
   let engine = templating::Engine::new(&config, &cli);
-  let mut state = State::read()?;
+  let dotfiles = config.dotfiles.clone();
+  let dry_run = cli.dry_run;
   match cli.command.clone() {
-    cli::Command::Link { link } => commands::Link::new(config, engine)
-      .execute((cli.bake(), link.bake(), &state.linked))
-      .map(|linked| state.linked = linked),
+    cli::Command::Link { link } => with_state(&dotfiles, dry_run, |state| commands::Link::new(config, engine).execute((cli.bake(), link.bake(), state))),
+    cli::Command::Unlink { unlink } => with_state(&dotfiles, dry_run, |state| commands::Unlink::new().execute((cli.bake(), unlink.bake(), state))),
+    cli::Command::Restore { restore } => with_state(&dotfiles, dry_run, |state| commands::Restore::new().execute((cli.bake(), restore.bake(), state))),
+    cli::Command::Status { status } => {
+      let _lock = state::Lock::acquire()?;
+      commands::Status::new(config, engine).execute((status.bake(), State::read(&dotfiles, dry_run)?))
+    }
+    cli::Command::Roots { roots } => with_state(&dotfiles, dry_run, |state| commands::Roots::new().execute((cli.bake(), roots, state))),
+    cli::Command::State { state: state_command } => with_state(&dotfiles, dry_run, |state| commands::State::new(config, engine).execute((cli.bake(), state_command, state))),
     cli::Command::Clone { repo } => commands::Clone::new(config).execute((cli, repo)),
-    cli::Command::Install { install } => commands::Install::new(config, engine).execute((cli.bake(), install.bake())),
+    cli::Command::Install { install } => with_state(&dotfiles, dry_run, |state| commands::Install::new(config, engine).execute((cli.bake(), install.bake(), state))),
+    cli::Command::Uninstall { uninstall } => with_state(&dotfiles, dry_run, |state| commands::Uninstall::new(config, engine).execute((cli.bake(), uninstall.bake(), state))),
+    cli::Command::Update { update } => with_state(&dotfiles, dry_run, |state| commands::Update::new(config, engine).execute((cli.bake(), update.bake(), state))),
     cli::Command::Init { repo } => commands::Init::new(config).execute((cli, repo)),
     cli::Command::Completions { shell } => commands::Completions::new().execute(shell),
-  }?;
+  }
+}
+
+/// Runs a command which changes the state of the dotfiles repository at `dotfiles` and writes the state afterwards
+///
+/// The state is locked and interrupts are handled while the command runs
+#[cfg_attr(feature = "profiling", instrument(skip(command)))]
+fn with_state(dotfiles: &Path, dry_run: bool, command: impl FnOnce(State) -> Result<State, miette::Report>) -> Result<(), miette::Report> {
+  let _lock = state::Lock::acquire()?;
+  state::handle_interrupts()?;
+
+  let state = command(State::read(dotfiles, dry_run)?)?;
+
+  if dry_run {
+    return ().pipe(Ok);
+  }
 
   state.write().map_err(Into::into)
 }
@@ -0,0 +1,140 @@
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tap::Pipe;
+#[cfg(feature = "profiling")]
+use tracing::instrument;
+use walkdir::WalkDir;
+
+use crate::PROJECT_DIRS;
+
+/// Locations of the backups of files replaced by rotz, keyed by the path they were moved from with the newest backup first
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(transparent)]
+pub(crate) struct Backups(pub HashMap<PathBuf, Vec<PathBuf>>);
+
+impl Backups {
+  /// Records `backup` as the newest backup of `target`
+  pub fn push(&mut self, target: PathBuf, backup: PathBuf) {
+    self.0.entry(target).or_default().insert(0, backup);
+  }
+
+  /// Newest backup of `target`
+  pub fn latest(&self, target: &Path) -> Option<&PathBuf> {
+    self.0.get(target).and_then(|backups| backups.first())
+  }
+
+  /// Forgets the newest backup of `target`, and the target once it has no backups left
+  pub fn pop(&mut self, target: &Path) {
+    if let Some(backups) = self.0.get_mut(target) {
+      if !backups.is_empty() {
+        backups.remove(0);
+      }
+      if backups.is_empty() {
+        self.0.remove(target);
+      }
+    }
+  }
+}
+
+/// Moves `target` into a new timestamped directory of the backup store and returns the backup location
+#[cfg_attr(feature = "profiling", instrument)]
+pub(crate) fn create(target: &Path) -> std::io::Result<PathBuf> {
+  let timestamp = super::timestamp();
+  let relative = target
+    .components()
+    .filter_map(|c| match c {
+      Component::Normal(c) => Some(c),
+      _ => None,
+    })
+    .collect::<PathBuf>();
+
+  let store = PROJECT_DIRS.data_local_dir().join("backups");
+  let mut backup = store.join(timestamp.to_string()).join(&relative);
+  let mut counter = 0;
+  while fs::symlink_metadata(&backup).is_ok() {
+    counter += 1;
+    backup = store.join(format!("{timestamp}-{counter}")).join(&relative);
+  }
+
+  move_path(target, &backup)?;
+
+  backup.pipe(Ok)
+}
+
+/// Moves a backup back to its original location and removes the directories of the backup store it leaves empty
+#[cfg_attr(feature = "profiling", instrument)]
+pub(crate) fn restore(backup: &Path, target: &Path) -> std::io::Result<()> {
+  move_path(backup, target)?;
+
+  let store = PROJECT_DIRS.data_local_dir().join("backups");
+  for directory in backup.ancestors().skip(1).take_while(|d| d.starts_with(&store) && *d != store) {
+    if fs::remove_dir(directory).is_err() {
+      break;
+    }
+  }
+
+  ().pipe(Ok)
+}
+
+fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+  if let Some(parent) = to.parent() {
+    fs::create_dir_all(parent)?;
+  }
+
+  match fs::rename(from, to) {
+    Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+      copy_path(from, to)?;
+      if from.is_dir() { fs::remove_dir_all(from) } else { fs::remove_file(from) }
+    }
+    result => result,
+  }
+}
+
+fn copy_path(from: &Path, to: &Path) -> std::io::Result<()> {
+  for entry in WalkDir::new(from) {
+    let entry = entry?;
+    let relative = entry.path().strip_prefix(from).map_err(std::io::Error::other)?;
+    let target = if relative.as_os_str().is_empty() { to.to_path_buf() } else { to.join(relative) };
+
+    if entry.file_type().is_dir() {
+      fs::create_dir_all(target)?;
+    } else {
+      fs::copy(entry.path(), target)?;
+    }
+  }
+
+  ().pipe(Ok)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::{Path, PathBuf};
+
+  use speculoos::prelude::*;
+
+  use super::Backups;
+
+  #[test]
+  fn newest_backup_first() {
+    let mut backups = Backups::default();
+    backups.push(PathBuf::from("/home/.gitconfig"), PathBuf::from("/backups/1/.gitconfig"));
+    backups.push(PathBuf::from("/home/.gitconfig"), PathBuf::from("/backups/2/.gitconfig"));
+
+    assert_that!(backups.latest(Path::new("/home/.gitconfig")))
+      .is_some()
+      .is_equal_to(&PathBuf::from("/backups/2/.gitconfig"));
+
+    backups.pop(Path::new("/home/.gitconfig"));
+    assert_that!(backups.latest(Path::new("/home/.gitconfig")))
+      .is_some()
+      .is_equal_to(&PathBuf::from("/backups/1/.gitconfig"));
+
+    backups.pop(Path::new("/home/.gitconfig"));
+    assert_that!(backups.0).is_empty();
+  }
+}
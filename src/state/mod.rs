@@ -1,22 +1,45 @@
-use std::{collections::HashMap, fmt::Debug, fs, path::PathBuf};
+use std::{
+  collections::HashMap,
+  fmt::Debug,
+  fs::{self, File, TryLockError},
+  io::Write,
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicBool, Ordering},
+  time::{SystemTime, UNIX_EPOCH},
+};
 
 use miette::Diagnostic;
-use serde::{Deserialize, Serialize};
-use tap::Pipe;
+use path_absolutize::Absolutize;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tap::{Pipe, Tap};
 #[cfg(feature = "profiling")]
 use tracing::instrument;
 
-use crate::{FILE_EXTENSIONS, FileFormat, PROJECT_DIRS, helpers};
+use crate::{FILE_EXTENSIONS, FileFormat, PROJECT_DIRS, config::LinkType, helpers};
+
+pub(crate) mod backups;
+pub(crate) use backups::Backups;
+mod v1;
+
+/// Version of the state file schema written by this version of rotz
+pub(crate) const VERSION: u32 = 2;
+
+/// Set when rotz receives an interrupt signal
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
 #[derive(thiserror::Error, Diagnostic, Debug)]
 pub(crate) enum Error {
-  #[error("Could not read state file")]
-  #[diagnostic(code(state::read))]
-  Reading(#[source] std::io::Error),
+  #[error("Could not read state file \"{0}\"")]
+  #[diagnostic(code(state::read), help("Check that the file is readable or move it away to start with an empty state"))]
+  Reading(PathBuf, #[source] std::io::Error),
 
-  #[error("Could not write state file")]
+  #[error("Could not write state file \"{0}\"")]
   #[diagnostic(code(state::write))]
-  Writing(#[source] std::io::Error),
+  Writing(PathBuf, #[source] std::io::Error),
+
+  #[error("Could not lock state file \"{0}\"")]
+  #[diagnostic(code(state::lock))]
+  Locking(PathBuf, #[source] std::io::Error),
 
   #[error("Could not serialize state")]
   #[diagnostic(code(state::serialize))]
@@ -26,55 +49,394 @@ pub(crate) enum Error {
     helpers::ParseError,
   ),
 
-  #[error("Could not deserialize state")]
-  #[diagnostic(code(state::deserialize))]
+  #[error("Could not deserialize state file \"{0}\"")]
+  #[diagnostic(
+    code(state::deserialize),
+    help("The state file is corrupted. Fix it or move it away to start with an empty state, rotz will then no longer know which links it created")
+  )]
   Deserializing(
+    PathBuf,
     #[source]
     #[diagnostic_source]
     helpers::ParseError,
   ),
+
+  #[error("Could not read the state directory \"{0}\"")]
+  #[diagnostic(code(state::roots))]
+  ReadingRoots(PathBuf, #[source] std::io::Error),
+
+  #[error("Could not remove the state of \"{0}\"")]
+  #[diagnostic(code(state::remove))]
+  Removing(PathBuf, #[source] std::io::Error),
+
+  #[error("Could not set up the interrupt handler")]
+  #[diagnostic(code(state::interrupt::handler))]
+  InterruptHandler(#[source] ctrlc::Error),
+
+  #[error("rotz was interrupted")]
+  #[diagnostic(code(state::interrupted), help("The changes made before the interruption were saved to the state"))]
+  Interrupted,
+
+  #[error("The state file has the unsupported version {0}")]
+  #[diagnostic(code(state::version), help("The state file was written by a newer version of rotz. Try updating rotz"))]
+  UnsupportedVersion(u32),
 }
 
+/// Links created by rotz keyed by dot and target path
 #[derive(Serialize, Deserialize, Default, Debug)]
 #[serde(transparent)]
-pub(crate) struct Linked(pub HashMap<String, HashMap<PathBuf, PathBuf>>);
+pub(crate) struct Linked(pub HashMap<String, HashMap<PathBuf, Link>>);
 
-impl From<HashMap<String, HashMap<PathBuf, PathBuf>>> for Linked {
-  fn from(value: HashMap<String, HashMap<PathBuf, PathBuf>>) -> Self {
-    Self(value)
+impl Linked {
+  /// Removes the link at `to` of the dot `name`, and the dot once it has no links left
+  pub fn remove(&mut self, name: &str, to: &Path) {
+    if let Some(links) = self.0.get_mut(name) {
+      links.remove(to);
+      if links.is_empty() {
+        self.0.remove(name);
+      }
+    }
   }
 }
 
+/// A link created by rotz
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(clippy::struct_field_names)]
+pub(crate) struct Link {
+  /// The dotfile the target was created from
+  pub source: PathBuf,
+  #[serde(rename = "type")]
+  pub link_type: LinkType,
+  /// Seconds since the unix epoch when the target was created
+  pub created: u64,
+  /// Hash of the content of the dotfile when the target was created
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub source_hash: Option<String>,
+  /// Hash of the content rotz wrote to the target for copied or rendered targets
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub target_hash: Option<String>,
+  /// Version of rotz which created the target
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub rotz_version: Option<String>,
+}
+
+/// Results of the last install of each dot
 #[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(transparent)]
+pub(crate) struct Installed(pub HashMap<String, Install>);
+
+/// Result of running the install command of a dot
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Install {
+  /// Hash of the rendered install command
+  #[serde(default)]
+  pub hash: String,
+  /// Seconds since the unix epoch when the install command finished
+  pub time: u64,
+  pub success: bool,
+  /// Version of rotz which ran the install command
+  pub rotz_version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub(crate) struct State {
+  pub version: u32,
+  /// The dotfiles repository this state belongs to
+  #[serde(default)]
+  pub dotfiles: PathBuf,
+  #[serde(default)]
   pub linked: Linked,
+  #[serde(default)]
+  pub installed: Installed,
+  #[serde(default)]
+  pub backups: Backups,
+  /// Whether changes are written to the state file when they are committed
+  #[serde(skip)]
+  persist: bool,
+}
+
+impl Default for State {
+  fn default() -> Self {
+    Self {
+      version: VERSION,
+      dotfiles: PathBuf::new(),
+      linked: Linked::default(),
+      installed: Installed::default(),
+      backups: Backups::default(),
+      persist: false,
+    }
+  }
+}
+
+/// Advisory lock on the state which is held until it is dropped
+#[derive(Debug)]
+pub(crate) struct Lock {
+  _file: File,
+}
+
+impl Lock {
+  /// Locks the state for this process, waiting for other rotz processes to finish
+  #[cfg_attr(feature = "profiling", instrument)]
+  pub fn acquire() -> Result<Self, Error> {
+    let path = PROJECT_DIRS.data_local_dir().join("state.lock");
+    fs::create_dir_all(PROJECT_DIRS.data_local_dir()).map_err(|e| Error::Locking(path.clone(), e))?;
+    let file = File::options().create(true).truncate(false).write(true).open(&path).map_err(|e| Error::Locking(path.clone(), e))?;
+
+    match file.try_lock() {
+      Ok(()) => {}
+      Err(TryLockError::WouldBlock) => {
+        eprintln!("Waiting for another rotz process to finish");
+        file.lock().map_err(|e| Error::Locking(path.clone(), e))?;
+      }
+      Err(TryLockError::Error(err)) => return Error::Locking(path, err).pipe(Err),
+    }
+
+    Self { _file: file }.pipe(Ok)
+  }
+}
+
+/// Version of a state file, files written before the version was recorded are version 1
+#[derive(Deserialize)]
+struct Version {
+  #[serde(default = "v1::version")]
+  version: u32,
 }
 
 impl State {
+  /// Reads the state of the dotfiles repository at `dotfiles`
+  ///
+  /// The links of a state file written before the state was kept per repository are taken over if their dotfiles are in `dotfiles`.
+  /// Nothing is written if `dry_run` is set
   #[cfg_attr(feature = "profiling", instrument)]
-  pub fn read() -> Result<State, Error> {
-    let state_file = helpers::get_file_with_format(PROJECT_DIRS.data_local_dir(), "state");
+  pub fn read(dotfiles: &Path, dry_run: bool) -> Result<State, Error> {
+    let dotfiles = resolve_root(dotfiles).unwrap_or_else(|_| dotfiles.to_path_buf());
+
+    if let Some((state_file, format)) = helpers::get_file_with_format(root_dir(&dotfiles), "state") {
+      return read_file(&state_file, format, !dry_run)?
+        .tap_mut(|s| {
+          s.dotfiles = dotfiles;
+          s.persist = !dry_run;
+        })
+        .pipe(Ok);
+    }
+
+    let Some((legacy_file, format)) = helpers::get_file_with_format(PROJECT_DIRS.data_local_dir(), "state") else {
+      return State {
+        dotfiles,
+        persist: !dry_run,
+        ..State::default()
+      }
+      .pipe(Ok);
+    };
 
-    if let Some((state_file, format)) = state_file {
-      deserialize_state(&fs::read_to_string(state_file).map_err(Error::Reading)?, format).map_err(Error::Deserializing)?
+    let mut legacy = read_file(&legacy_file, format, !dry_run)?;
+    let mut state = legacy.take_root(dotfiles);
+    state.persist = !dry_run;
+
+    if dry_run {
+      return state.pipe(Ok);
+    }
+
+    if !state.is_empty() {
+      state.write_to(&root_dir(&state.dotfiles).join(legacy_file.file_name().unwrap_or_default()), format)?;
+    }
+
+    if legacy.is_empty() {
+      let backup = legacy_file.with_extension(format!("unscoped.{format}"));
+      fs::rename(&legacy_file, &backup).map_err(|e| Error::Writing(backup, e))?;
     } else {
-      State::default()
+      legacy.write_to(&legacy_file, format)?;
     }
-    .pipe(Ok)
+
+    state.pipe(Ok)
   }
 
   #[cfg_attr(feature = "profiling", instrument)]
   pub fn write(&self) -> Result<(), Error> {
-    let state_file =
-      helpers::get_file_with_format(PROJECT_DIRS.data_local_dir(), "state").unwrap_or_else(|| (PROJECT_DIRS.data_local_dir().join(format!("state.{}", FILE_EXTENSIONS[0].0)), FILE_EXTENSIONS[0].1));
+    let directory = root_dir(&self.dotfiles);
+    let state_file = helpers::get_file_with_format(&directory, "state");
+
+    if state_file.is_none() && self.is_empty() {
+      return ().pipe(Ok);
+    }
+
+    let (state_file, format) = state_file.unwrap_or_else(|| (directory.join(format!("state.{}", FILE_EXTENSIONS[0].0)), FILE_EXTENSIONS[0].1));
+    self.write_to(&state_file, format)
+  }
+
+  /// Writes the state after each change so it is not lost if rotz is interrupted, does nothing on dry runs
+  #[cfg_attr(feature = "profiling", instrument)]
+  pub fn commit(&self) -> Result<(), Error> {
+    if !self.persist {
+      return ().pipe(Ok);
+    }
+
+    self.write()
+  }
+
+  fn write_to(&self, state_file: &Path, format: FileFormat) -> Result<(), Error> {
+    if let Some(parent) = state_file.parent() {
+      fs::create_dir_all(parent).map_err(|e| Error::Writing(state_file.to_path_buf(), e))?;
+    }
+    write_atomically(state_file, serialize_state(self, format).map_err(Error::Serializing)?.as_bytes()).map_err(|e| Error::Writing(state_file.to_path_buf(), e))
+  }
+
+  /// Whether rotz has not recorded anything in this state
+  pub fn is_empty(&self) -> bool {
+    self.linked.0.is_empty() && self.installed.0.is_empty() && self.backups.0.is_empty()
+  }
+
+  /// Moves the dots which belong to the dotfiles repository at `dotfiles` into a new state
+  ///
+  /// Links belong to the repository if their source is in it, install results and backups follow the dots and links they belong to
+  #[cfg_attr(feature = "profiling", instrument)]
+  fn take_root(&mut self, dotfiles: PathBuf) -> State {
+    let mut state = State { dotfiles, ..State::default() };
+
+    let names = self
+      .linked
+      .0
+      .iter()
+      .filter(|(_, links)| {
+        links
+          .values()
+          .any(|l| l.source.starts_with(&state.dotfiles) || resolve_root(&l.source).is_ok_and(|source| source.starts_with(&state.dotfiles)))
+      })
+      .map(|(name, _)| name.clone())
+      .collect::<Vec<_>>();
+    for name in names {
+      if let Some(links) = self.linked.0.remove(&name) {
+        state.linked.0.insert(name, links);
+      }
+    }
+
+    let names = self
+      .installed
+      .0
+      .keys()
+      .filter(|name| state.linked.0.contains_key(*name) || state.dotfiles.join(name.trim_start_matches('/')).exists())
+      .cloned()
+      .collect::<Vec<_>>();
+    for name in names {
+      if let Some(install) = self.installed.0.remove(&name) {
+        state.installed.0.insert(name, install);
+      }
+    }
 
-    fs::create_dir_all(PROJECT_DIRS.data_local_dir()).map_err(Error::Writing)?;
-    fs::write(state_file.0, serialize_state(self, state_file.1).map_err(Error::Serializing)?).map_err(Error::Writing)
+    let targets = self
+      .backups
+      .0
+      .keys()
+      .filter(|target| state.linked.0.values().any(|l| l.contains_key(*target)))
+      .cloned()
+      .collect::<Vec<_>>();
+    for target in targets {
+      if let Some(backup) = self.backups.0.remove(&target) {
+        state.backups.0.insert(target, backup);
+      }
+    }
+
+    state
+  }
+}
+
+/// Lets the first interrupt stop rotz after the change it is making, the second one exits immediately
+///
+/// Changes are committed to the state as they are made so the state matches the filesystem when rotz stops
+#[cfg_attr(feature = "profiling", instrument)]
+pub(crate) fn handle_interrupts() -> Result<(), Error> {
+  ctrlc::set_handler(|| {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+      std::process::exit(130);
+    }
+    eprintln!("\nStopping after the current change, interrupt again to exit immediately");
+  })
+  .map_err(Error::InterruptHandler)
+}
+
+/// Fails if rotz was interrupted
+pub(crate) fn check_interrupted() -> Result<(), Error> {
+  if INTERRUPTED.load(Ordering::SeqCst) { Error::Interrupted.pipe(Err) } else { ().pipe(Ok) }
+}
+
+/// Path the state of the dotfiles repository at `dotfiles` is keyed by
+///
+/// Symbolic links are resolved so every path to the same repository shares its state, paths which do not exist anymore are only made absolute
+pub(crate) fn resolve_root(dotfiles: &Path) -> std::io::Result<PathBuf> {
+  dotfiles.canonicalize().or_else(|_| dotfiles.absolutize().map(|d| d.to_path_buf()))
+}
+
+/// Directory the state of the dotfiles repository at `dotfiles` is kept in
+fn root_dir(dotfiles: &Path) -> PathBuf {
+  let hash = helpers::hash(dotfiles.to_string_lossy().as_bytes());
+  PROJECT_DIRS.data_local_dir().join("roots").join(&hash[..16])
+}
+
+/// Reads the states of all dotfiles repositories rotz knows about
+#[cfg_attr(feature = "profiling", instrument)]
+pub(crate) fn roots() -> Result<Vec<State>, Error> {
+  let directory = PROJECT_DIRS.data_local_dir().join("roots");
+  if !directory.exists() {
+    return Vec::new().pipe(Ok);
+  }
+
+  let mut roots = Vec::new();
+  for entry in fs::read_dir(&directory).map_err(|e| Error::ReadingRoots(directory.clone(), e))? {
+    let entry = entry.map_err(|e| Error::ReadingRoots(directory.clone(), e))?;
+    if let Some((state_file, format)) = helpers::get_file_with_format(entry.path(), "state") {
+      roots.push(read_file(&state_file, format, false)?);
+    }
+  }
+
+  roots.sort_by(|a, b| a.dotfiles.cmp(&b.dotfiles));
+  roots.pipe(Ok)
+}
+
+/// Removes the state of the dotfiles repository at `dotfiles`
+#[cfg_attr(feature = "profiling", instrument)]
+pub(crate) fn remove_root(dotfiles: &Path) -> Result<(), Error> {
+  let directory = root_dir(dotfiles);
+  if !directory.exists() {
+    return ().pipe(Ok);
+  }
+
+  fs::remove_dir_all(&directory).map_err(|e| Error::Removing(dotfiles.to_path_buf(), e))
+}
+
+/// Parses the content of the state file `state_file` and migrates it to the current version, returns the version it was written with
+#[cfg_attr(feature = "profiling", instrument)]
+pub(crate) fn parse(content: &str, format: FileFormat, state_file: &Path) -> Result<(State, u32), Error> {
+  let version = deserialize_state::<Version>(content, format).map_err(|e| Error::Deserializing(state_file.to_path_buf(), e))?.version;
+
+  let state: State = match version {
+    VERSION => deserialize_state(content, format).map_err(|e| Error::Deserializing(state_file.to_path_buf(), e))?,
+    1 => deserialize_state::<v1::State>(content, format).map_err(|e| Error::Deserializing(state_file.to_path_buf(), e))?.into(),
+    _ => return Error::UnsupportedVersion(version).pipe(Err),
+  };
+
+  (state, version).pipe(Ok)
+}
+
+/// Reads a state file migrating it to the current version if it was written by an older version of rotz
+///
+/// The file is copied to `state.v{version}.{format}` before it is migrated if `keep_old` is set
+#[cfg_attr(feature = "profiling", instrument)]
+fn read_file(state_file: &Path, format: FileFormat, keep_old: bool) -> Result<State, Error> {
+  let content = fs::read_to_string(state_file).map_err(|e| Error::Reading(state_file.to_path_buf(), e))?;
+  let (state, version) = parse(&content, format, state_file)?;
+
+  if version == VERSION || !keep_old {
+    return state.pipe(Ok);
   }
+
+  let backup = state_file.with_extension(format!("v{version}.{format}"));
+  fs::copy(state_file, &backup).map_err(|e| Error::Writing(backup, e))?;
+
+  state.pipe(Ok)
 }
 
 #[cfg_attr(feature = "profiling", instrument)]
-fn deserialize_state(state: &str, format: FileFormat) -> Result<State, helpers::ParseError> {
+pub(crate) fn deserialize_state<T: DeserializeOwned>(state: &str, format: FileFormat) -> Result<T, helpers::ParseError> {
   Ok(match format {
     #[cfg(feature = "yaml")]
     FileFormat::Yaml => serde_yaml::from_str(state)?,
@@ -86,7 +448,7 @@ fn deserialize_state(state: &str, format: FileFormat) -> Result<State, helpers::
 }
 
 #[cfg_attr(feature = "profiling", instrument)]
-fn serialize_state(state: &(impl Serialize + Debug), format: FileFormat) -> Result<String, helpers::ParseError> {
+pub(crate) fn serialize_state(state: &(impl Serialize + Debug), format: FileFormat) -> Result<String, helpers::ParseError> {
   Ok(match format {
     #[cfg(feature = "yaml")]
     FileFormat::Yaml => serde_yaml::to_string(state)?,
@@ -96,3 +458,87 @@ fn serialize_state(state: &(impl Serialize + Debug), format: FileFormat) -> Resu
     FileFormat::Json => serde_json::to_string(state)?,
   })
 }
+
+/// Seconds since the unix epoch
+pub(crate) fn timestamp() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Writes to a temporary file next to `path` and renames it into place so the file is never left partially written
+fn write_atomically(path: &Path, content: &[u8]) -> std::io::Result<()> {
+  let temporary = path.with_extension(format!("{}.tmp", path.extension().unwrap_or_default().to_string_lossy()));
+
+  let mut file = File::create(&temporary)?;
+  file.write_all(content)?;
+  file.sync_all()?;
+  drop(file);
+
+  fs::rename(&temporary, path).inspect_err(|_| {
+    fs::remove_file(&temporary).ok();
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use speculoos::prelude::*;
+  use velcro::hash_map;
+
+  use super::{Install, Link, State, resolve_root};
+  use crate::config::LinkType;
+
+  fn link(source: &str) -> Link {
+    Link {
+      source: PathBuf::from(source),
+      link_type: LinkType::Symbolic,
+      created: 0,
+      source_hash: None,
+      target_hash: None,
+      rotz_version: None,
+    }
+  }
+
+  #[test]
+  fn take_root() {
+    let mut legacy = State::default();
+    legacy.linked.0 = hash_map! {
+      "/git".to_owned(): hash_map! { PathBuf::from("/home/.gitconfig"): link("/dotfiles/git/.gitconfig") },
+      "/nvim".to_owned(): hash_map! { PathBuf::from("/home/.config/nvim"): link("/other/nvim/config") },
+    };
+    legacy.installed.0 = hash_map! {
+      "/git".to_owned(): Install { hash: String::new(), time: 0, success: true, rotz_version: String::new() },
+    };
+    legacy.backups.0 = hash_map! {
+      PathBuf::from("/home/.gitconfig"): vec![PathBuf::from("/backups/.gitconfig")],
+      PathBuf::from("/home/.config/nvim"): vec![PathBuf::from("/backups/nvim")],
+    };
+
+    let state = legacy.take_root(PathBuf::from("/dotfiles"));
+
+    assert_that!(state.dotfiles).is_equal_to(PathBuf::from("/dotfiles"));
+    assert_that!(state.linked.0).contains_key("/git".to_owned());
+    assert_that!(state.linked.0).has_length(1);
+    assert_that!(state.installed.0).contains_key("/git".to_owned());
+    assert_that!(state.backups.0).contains_key(PathBuf::from("/home/.gitconfig"));
+    assert_that!(state.backups.0).has_length(1);
+
+    assert_that!(legacy.linked.0).contains_key("/nvim".to_owned());
+    assert_that!(legacy.linked.0).has_length(1);
+    assert_that!(legacy.installed.0).is_empty();
+    assert_that!(legacy.backups.0).has_length(1);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn resolve_root_follows_symlinks() {
+    let dir = tempfile::tempdir().unwrap();
+    let dotfiles = dir.path().join("dotfiles");
+    let symlink = dir.path().join("symlink");
+    std::fs::create_dir(&dotfiles).unwrap();
+    std::os::unix::fs::symlink(&dotfiles, &symlink).unwrap();
+
+    assert_that!(resolve_root(&symlink).unwrap()).is_equal_to(resolve_root(&dotfiles).unwrap());
+    assert_that!(resolve_root(&dir.path().join("missing")).unwrap()).is_equal_to(dir.path().join("missing"));
+  }
+}
@@ -0,0 +1,101 @@
+use std::{collections::HashMap, fs, path::PathBuf, time::UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use super::{Backups, Link, Linked};
+use crate::config::LinkType;
+
+pub(super) const fn version() -> u32 {
+  1
+}
+
+/// State file written before the schema was versioned
+#[derive(Deserialize)]
+pub(super) struct State {
+  #[serde(default)]
+  linked: HashMap<String, HashMap<PathBuf, PathBuf>>,
+  #[serde(default)]
+  hashes: HashMap<PathBuf, String>,
+  #[serde(default)]
+  backups: Backups,
+}
+
+impl From<State> for super::State {
+  fn from(state: State) -> Self {
+    let State { linked, mut hashes, backups } = state;
+
+    let linked = linked
+      .into_iter()
+      .map(|(name, links)| {
+        let links = links
+          .into_iter()
+          .map(|(target, source)| {
+            let metadata = fs::symlink_metadata(&target).ok();
+            let target_hash = hashes.remove(&target);
+
+            let link_type = if target_hash.is_some() {
+              LinkType::Copy
+            } else if metadata.as_ref().is_some_and(fs::Metadata::is_symlink) {
+              LinkType::Symbolic
+            } else {
+              LinkType::Hard
+            };
+
+            let created = metadata.and_then(|m| m.modified().ok()).and_then(|m| m.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_secs());
+
+            let link = Link {
+              source,
+              link_type,
+              created,
+              source_hash: None,
+              target_hash,
+              rotz_version: None,
+            };
+
+            (target, link)
+          })
+          .collect();
+
+        (name, links)
+      })
+      .collect();
+
+    Self {
+      linked: Linked(linked),
+      backups,
+      ..Self::default()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use speculoos::prelude::*;
+
+  use crate::{
+    FileFormat,
+    config::LinkType,
+    state::{self, Version, deserialize_state},
+  };
+
+  #[test]
+  fn migrate() {
+    let content = "linked:\n  /git:\n    /nonexistent/.gitconfig: /dotfiles/git/.gitconfig\n    /nonexistent/.gitignore: /dotfiles/git/.gitignore\nhashes:\n  /nonexistent/.gitignore: abc\n";
+
+    assert_that!(deserialize_state::<Version>(content, FileFormat::Yaml).map(|v| v.version)).is_ok().is_equal_to(1);
+
+    let state: state::State = deserialize_state::<super::State>(content, FileFormat::Yaml).unwrap().into();
+    assert_that!(state.version).is_equal_to(state::VERSION);
+
+    let git = state.linked.0.get("/git").unwrap();
+    let config = git.get(&PathBuf::from("/nonexistent/.gitconfig")).unwrap();
+    assert_that!(config.source).is_equal_to(PathBuf::from("/dotfiles/git/.gitconfig"));
+    assert_that!(config.target_hash).is_none();
+
+    let ignore = git.get(&PathBuf::from("/nonexistent/.gitignore")).unwrap();
+    assert_that!(ignore.link_type).is_equal_to(LinkType::Copy);
+    assert_that!(ignore.target_hash).is_some().is_equal_to("abc".to_owned());
+  }
+}
@@ -34,6 +34,7 @@ fn templating(#[case] template: &str, #[case] expected: &str) {
   let config = Config {
     dotfiles: "dotfiles".into(),
     link_type: LinkType::Hard,
+    relative_links: false,
     shell_command: "shell_command".to_owned().into(),
     variables: map! {
       "test".to_owned() => "test".into(),